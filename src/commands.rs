@@ -1,69 +1,238 @@
-use crate::commands::utils::{find_files, get_cwd, load_series_list, save_series_list};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use crate::commands::discovery::find_files;
+pub(crate) use crate::commands::listing::{ListFilter, ListSort};
+use crate::commands::player::{PlaybackStart, Player};
+use crate::commands::utils::{get_cwd, load_series_list, save_series_list};
 use crate::errors::{Result, UpNextError};
-use crate::schema::{Series, SeriesList};
+use crate::history::{self, HistoryEntry};
+use crate::journal;
+use crate::output::{self, note};
+use crate::schema::{Series, SeriesList, SeriesStatus, Settings, SortOrder, WatchedThreshold};
 
-pub(super) fn print_current_series_info() -> Result<()> {
-    let series_list = load_series_list()?;
-    let current_dir = get_cwd()?;
-    let series = series_list.find_series(&current_dir)?;
-    Ok(println!("{series}"))
+pub(super) fn print_current_series_info(selector: Option<&str>) -> Result<()> {
+    let mut series_list = load_series_list()?;
+    let series_path = utils::series_path(&series_list, selector)?;
+    let series = series_list.find_series(&series_path)?;
+    let settings = utils::effective_settings(&series_list, series, Settings::default())?;
+    // The info is still useful if the directory can not be read
+    let files = find_files(series, &settings).ok();
+    let series = series_list.find_series_mut(&series_path)?;
+    if let Some(files) = &files {
+        if tracking::resync(series, files, settings.sort(), false)? {
+            save_series_list(&series_list)?;
+        }
+    }
+    let series = series_list.find_series(&series_path)?;
+    if output::is_json() {
+        let next = files.as_ref().and_then(|files| {
+            let i = usize::try_from(series.next_episode - 1).ok()?;
+            Some(json!({
+                "number": i + 1,
+                "episode": discovery::episode_name(files, i).map(|name| name.to_string()),
+                "file": discovery::relative_name(series, files.get(i)?),
+                "sort": settings.sort().to_string(),
+            }))
+        });
+        let series = utils::series_json(&series_list, &series_path)?;
+        return output::print_json(&json!({ "series": series, "next": next }));
+    }
+    println!("{series}");
+    if let Some(files) = files {
+        let index = usize::try_from(series.next_episode - 1).ok();
+        match index.and_then(|i| files.get(i).map(|file_path| (i, file_path))) {
+            Some((i, file_path)) => {
+                let file_name = file_path
+                    .strip_prefix(&series.path)
+                    .unwrap_or(file_path)
+                    .display();
+                let episode = match discovery::episode_name(&files, i) {
+                    Some(episode_name) => format!("{episode_name} "),
+                    None => String::new(),
+                };
+                println!(
+                    "Next: {episode}\"{file_name}\" ({} of {}, {} order)",
+                    i + 1,
+                    files.len(),
+                    settings.sort()
+                );
+            }
+            None => println!("Next: no episodes left ({} in total)", files.len()),
+        }
+    }
+    Ok(())
 }
 
-pub(super) fn print_all_series_info() -> Result<()> {
-    let content = std::fs::read_to_string(crate::utils::get_toml_path()?)?;
-    Ok(println!("{content}"))
+/// Print a table of the series, dropped ones only if `all`, or the toml file as it is if `raw`.
+pub(super) fn print_all_series_info(
+    raw: bool,
+    sort: Option<ListSort>,
+    filter: Option<ListFilter>,
+    all: bool,
+) -> Result<()> {
+    if raw {
+        if output::is_json() {
+            return Err(UpNextError::GenericError(
+                "`list --raw` prints the toml file, it has no JSON output".to_string(),
+            ));
+        }
+        let content = std::fs::read_to_string(crate::utils::get_toml_path()?)?;
+        return Ok(println!("{content}"));
+    }
+    let series_list = load_series_list()?;
+    if output::is_json() {
+        let mut rows = listing::rows(&series_list);
+        listing::filter_and_sort(&mut rows, filter, sort, all);
+        let series: Vec<_> = rows.iter().map(listing::Row::to_json).collect();
+        return output::print_json(&json!({ "series": series }));
+    }
+    if series_list.series.is_empty() {
+        return Ok(println!(
+            "No series yet, run `{} init` in the directory of a series.",
+            crate::APP_NAME
+        ));
+    }
+    let mut rows = listing::rows(&series_list);
+    listing::filter_and_sort(&mut rows, filter, sort, all);
+    if rows.is_empty() {
+        return Ok(println!("No series match the filter."));
+    }
+    let colour = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    Ok(listing::print_table(&rows, colour))
 }
 
-pub(super) fn init() -> Result<()> {
+pub(super) fn init(recursive: bool) -> Result<()> {
     let mut series_list: SeriesList = load_series_list().unwrap_or_else(|_| SeriesList::new());
     let current_dir = get_cwd()?;
 
     series_list.add_series(current_dir.clone())?;
+    let series = series_list.find_series_mut(&current_dir)?;
+    series.name = Some(naming::series_name(series.directory_name()));
+    if recursive {
+        series.settings.recursive = Some(true);
+    }
+    let description = format!("`init` on \"{}\"", series.title());
     let series = series_list
         .series
         .last()
         .ok_or_else(|| UpNextError::GenericError("Could not get last series".to_string()))?;
-    save_series_list(&series_list)?;
+    utils::save_series_list_with_undo(&series_list, description)?;
     Ok(println!("{series}"))
 }
 
-pub(super) fn increment(n: i64) -> Result<()> {
+pub(super) fn increment(selector: Option<&str>, n: i64) -> Result<()> {
     let mut series_list = load_series_list()?;
-    let current_dir = get_cwd()?;
-    let series = series_list.find_series_mut(&current_dir)?;
-    println!("{series}");
+    let series_path = utils::series_path(&series_list, selector)?;
+    let files = utils::find_series_files(&series_list, &series_path);
+    let series = series_list.find_series_mut(&series_path)?;
+    let previous_next_episode = series.next_episode;
+    if !output::is_json() {
+        println!("{series}");
+    }
     series.next_episode += n;
-    save_series_list(&series_list)?;
+    series.position_seconds = None;
+    tracking::record_next_episode(series, files.as_deref());
+    if let Some(files) = &files {
+        tracking::update_completion(series, files.len());
+    }
+    let description = format!("`inc {n}` on \"{}\"", series.title());
+    utils::save_series_list_with_undo(&series_list, description)?;
 
-    let series = series_list.find_series(&current_dir)?;
-    Ok(println!("{series}"))
+    utils::print_changed_series(&series_list, &series_path, previous_next_episode)
 }
 
-pub(super) fn set_next_episode(n: u32) -> Result<()> {
+pub(super) fn set_next_episode(selector: Option<&str>, n: u32) -> Result<()> {
     let mut series_list = load_series_list()?;
-    let current_dir = get_cwd()?;
-    let series = series_list.find_series_mut(&current_dir)?;
-    println!("{series}");
+    let series_path = utils::series_path(&series_list, selector)?;
+    let files = utils::find_series_files(&series_list, &series_path);
+    let series = series_list.find_series_mut(&series_path)?;
+    let previous_next_episode = series.next_episode;
+    if !output::is_json() {
+        println!("{series}");
+    }
 
     series.next_episode = i64::from(n);
+    series.position_seconds = None;
+    tracking::record_next_episode(series, files.as_deref());
+    if let Some(files) = &files {
+        tracking::update_completion(series, files.len());
+    }
+    let description = format!("`set {n}` on \"{}\"", series.title());
+    utils::save_series_list_with_undo(&series_list, description)?;
+
+    utils::print_changed_series(&series_list, &series_path, previous_next_episode)
+}
+
+/// Set the display name of the series, an empty name goes back to the directory name. The
+/// aliases are replaced if any are given.
+pub(super) fn rename(selector: Option<&str>, name: &str, aliases: &[String]) -> Result<()> {
+    let mut series_list = load_series_list()?;
+    let series_path = utils::series_path(&series_list, selector)?;
+    let series = series_list.find_series_mut(&series_path)?;
+    println!("{series}");
+
+    series.name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+    if !aliases.is_empty() {
+        series.aliases = Some(aliases.to_vec());
+    }
     save_series_list(&series_list)?;
 
-    let series = series_list.find_series(&current_dir)?;
+    let series = series_list.find_series(&series_path)?;
     Ok(println!("{series}"))
 }
 
-pub(super) fn remove() -> Result<()> {
+/// Set the status of the series. Watching is the default, so it is not stored.
+pub(super) fn set_status(selector: Option<&str>, status: SeriesStatus) -> Result<()> {
     let mut series_list = load_series_list()?;
-    let current_dir = get_cwd()?;
-    let series = series_list.find_series(&current_dir)?;
+    let series_path = utils::series_path(&series_list, selector)?;
+    let series = series_list.find_series_mut(&series_path)?;
+    series.status = Some(status).filter(|status| *status != SeriesStatus::Watching);
+    save_series_list(&series_list)?;
+
+    let series = series_list.find_series(&series_path)?;
+    Ok(println!("\"{}\" is now {status}.", series.title()))
+}
+
+pub(super) fn remove(selector: Option<&str>) -> Result<()> {
+    let mut series_list = load_series_list()?;
+    let series_path = utils::series_path(&series_list, selector)?;
+    let series = series_list.find_series(&series_path)?;
     println!("{series}");
+    let description = format!("`remove` on \"{}\"", series.title());
 
-    series_list.remove_series(&get_cwd()?);
-    save_series_list(&series_list)?;
+    series_list.remove_series(&series_path);
+    utils::save_series_list_with_undo(&series_list, description)?;
 
     Ok(println!("Series removed."))
 }
 
+/// Restore the series changed by the last `init`, `inc`, `set`, `next`, `play` or `remove`.
+pub(super) fn undo() -> Result<()> {
+    utils::lock_series_list()?;
+    let mut journal = journal::read()?;
+    let Some(change) = journal.undo.pop() else {
+        return Ok(println!("Nothing to undo."));
+    };
+    utils::restore(&change, true)?;
+    journal.redo.push(change);
+    journal::write(&journal)
+}
+
+/// Apply the last undone change again.
+pub(super) fn redo() -> Result<()> {
+    utils::lock_series_list()?;
+    let mut journal = journal::read()?;
+    let Some(change) = journal.redo.pop() else {
+        return Ok(println!("Nothing to redo."));
+    };
+    utils::restore(&change, false)?;
+    journal.undo.push(change);
+    journal::write(&journal)
+}
+
 pub(super) fn edit_in_default_editor() -> Result<()> {
     let path = crate::utils::get_toml_path()?;
     println!("Opening the toml file in the default editor.");
@@ -71,169 +240,570 @@ pub(super) fn edit_in_default_editor() -> Result<()> {
     Ok(())
 }
 
-// TODO when testing also test ignore casing
-#[allow(clippy::needless_for_each)]
-pub(super) fn find_series(search_term: &str) -> Result<()> {
+/// Print the episodes found in the series directory in playing order, marking the next one,
+/// and the entries which were ignored with the reason.
+pub(super) fn list_files() -> Result<()> {
     let series_list = load_series_list()?;
-    let lower_search_term = search_term.to_lowercase();
-    let found_series: Vec<&Series> = series_list
-        .series
-        .iter()
-        .filter(|s| s.path.to_lowercase().contains(&lower_search_term))
-        .collect();
-    if found_series.is_empty() {
-        println!("No series found with the search term: {search_term}");
-    } else {
-        found_series.iter().for_each(|s| println!("{s}"));
+    let current_dir = get_cwd()?;
+    let series = series_list.find_series(&current_dir)?;
+    let settings = utils::effective_settings(&series_list, series, Settings::default())?;
+    let scan = discovery::scan(series, &settings)?;
+    println!(
+        "{} episodes in {} ({} order):",
+        scan.episodes.len(),
+        series.path,
+        settings.sort()
+    );
+    for (i, file_path) in scan.episodes.iter().enumerate() {
+        let marker = if i64::try_from(i)? + 1 == series.next_episode {
+            ">"
+        } else {
+            " "
+        };
+        let episode = discovery::episode_name(&scan.episodes, i)
+            .map(|episode_name| episode_name.to_string())
+            .unwrap_or_default();
+        println!(
+            "{marker}{:>4}  {episode:<11}{}",
+            i + 1,
+            discovery::relative_name(series, file_path)
+        );
+    }
+    if !scan.ignored.is_empty() {
+        println!("\nIgnored:");
+        for (path, reason) in &scan.ignored {
+            println!("  {} ({reason})", discovery::relative_name(series, path));
+        }
     }
     Ok(())
 }
 
-pub(super) fn print_toml_path() -> Result<()> {
-    Ok(println!("{}", crate::utils::get_toml_path()?))
+/// List the unfinished series, most recently watched first, and play the next episode of the
+/// one picked.
+pub(super) fn queue(all: bool, overrides: Settings) -> Result<()> {
+    let series_list = load_series_list()?;
+    let mut queue = vec![];
+    for series in &series_list.series {
+        let on_hold = matches!(
+            series.status(),
+            SeriesStatus::Paused | SeriesStatus::Dropped
+        );
+        if on_hold && !all {
+            continue;
+        }
+        // Series which can not be listed, e.g. on an unmounted drive, are left out
+        let Some(files) = utils::find_series_files(&series_list, &series.path) else {
+            continue;
+        };
+        let Some(index) = usize::try_from(series.next_episode - 1)
+            .ok()
+            .filter(|i| *i < files.len())
+        else {
+            continue;
+        };
+        let episode = match discovery::episode_name(&files, index) {
+            Some(episode_name) => format!("{episode_name} "),
+            None => String::new(),
+        };
+        let next = format!(
+            "{episode}\"{}\"",
+            discovery::relative_name(series, &files[index])
+        );
+        queue.push((series, next));
+    }
+    if queue.is_empty() {
+        return Ok(println!("Nothing to continue, all series are finished."));
+    }
+    // Most recent first, never watched series last
+    queue.sort_by_key(|(series, _)| std::cmp::Reverse(series.last_watched));
+
+    println!("Continue watching:");
+    for (i, (series, next)) in queue.iter().enumerate() {
+        let last_watched = match series.last_watched {
+            Some(time) => format!("last watched {}", time.format("%Y-%m-%d %H:%M")),
+            None => "not started".to_string(),
+        };
+        println!("{:>4}  {}: {next} ({last_watched})", i + 1, series.title());
+    }
+    let question = format!("Play which one? [1-{}, empty to quit]", queue.len());
+    let Some(answer) = utils::ask(&question)?.filter(|answer| !answer.is_empty()) else {
+        return Ok(());
+    };
+    let (series, _) = answer
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| queue.get(i.checked_sub(1)?))
+        .ok_or_else(|| UpNextError::GenericError(format!("No series number {answer}")))?;
+    play_next_episode_of(&series.path, overrides)
 }
 
-pub(super) fn play_next_episode() -> Result<()> {
+/// Write the episodes in their current order as the manual order of the series, to its
+/// `order` list if it has one or else to its `.upnext-order` file, and sort it manually.
+pub(super) fn regenerate_order() -> Result<()> {
     let mut series_list = load_series_list()?;
     let current_dir = get_cwd()?;
+    let series = series_list.find_series(&current_dir)?;
+    let settings = utils::effective_settings(&series_list, series, Settings::default())?;
+    let base_sort = match settings.sort() {
+        SortOrder::Manual => SortOrder::Natural,
+        sort => sort,
+    };
+    let settings = Settings {
+        sort: Some(base_sort),
+        ..settings
+    };
+    let files = find_files(series, &settings)?;
     let series = series_list.find_series_mut(&current_dir)?;
-    println!("{series}");
-
-    let files = find_files(&series.path)?;
-    if series.next_episode > i64::try_from(files.len())? {
-        Err(UpNextError::SeriesOver)
+    if series.settings.order.is_some() {
+        let order = files
+            .iter()
+            .map(|file_path| discovery::relative_name(series, file_path))
+            .collect();
+        series.settings.order = Some(order);
+        println!(
+            "Wrote the {base_sort} order of {} episodes to the `order` list of the series.",
+            files.len()
+        );
     } else {
-        let file_path = &files[usize::try_from(series.next_episode)? - 1];
-        utils::warn_on_episode_number_mismatch(file_path, series.next_episode)?;
-        player::play_in_vlc(file_path)?;
-
-        series.next_episode += 1;
-        save_series_list(&series_list)?;
-
-        let series = series_list.find_series(&current_dir)?;
-        Ok(println!("{series}"))
+        discovery::write_order_file(series, &files)?;
+        println!(
+            "Wrote the {base_sort} order of {} episodes to {}.",
+            files.len(),
+            discovery::order_file_path(series).display()
+        );
     }
+    series.settings.sort = Some(SortOrder::Manual);
+    save_series_list(&series_list)?;
+    let series = series_list.find_series(&current_dir)?;
+    Ok(println!("{series}"))
 }
 
-pub(super) fn play(episode_delay_seconds: u64) -> Result<()> {
+/// Open the manual order of the series in an editor, creating the `.upnext-order` file from
+/// the current order first if there is none yet.
+pub(super) fn edit_order() -> Result<()> {
     let mut series_list = load_series_list()?;
     let current_dir = get_cwd()?;
-    let i = series_list.find_series_index(&current_dir)?;
-    let series = series_list.at_mut(i)?;
-    let files = find_files(&series.path)?;
-
-    println!("{series}");
-    if series_list.at(i)?.next_episode <= i64::try_from(files.len())? {
-        let series = series_list.at_mut(i)?;
-        let file_path = &files[usize::try_from(series.next_episode)? - 1];
-        utils::warn_on_episode_number_mismatch(file_path, series.next_episode)?;
-        player::play_in_vlc(file_path)?;
-        series.next_episode += 1;
-        save_series_list(&series_list)?;
-        let series = series_list.find_series(&current_dir)?;
-        println!("{series}");
+    let series = series_list.find_series(&current_dir)?;
+    if series.settings.order.is_some() {
+        println!("The order of the series is the `order` list in the toml file.");
+        return utils::open_in_editor(&crate::utils::get_toml_path()?);
     }
-    while series_list.at(i)?.next_episode <= i64::try_from(files.len())? {
-        let series = series_list.at_mut(i)?;
-        player::countdown_to_next_episode(episode_delay_seconds);
-        let file_path = &files[usize::try_from(series.next_episode)? - 1];
-        player::play_in_vlc(file_path)?;
-        series.next_episode += 1;
+    if discovery::manual_order(series)?.is_none() {
+        return regenerate_order().and_then(|()| edit_order());
+    }
+    let order_file_path = discovery::order_file_path(series);
+    if series.settings.sort != Some(SortOrder::Manual) {
+        series_list.find_series_mut(&current_dir)?.settings.sort = Some(SortOrder::Manual);
         save_series_list(&series_list)?;
-        let series = series_list.find_series(&current_dir)?;
-        println!("{series}");
     }
-
-    Err(UpNextError::SeriesOver)
+    utils::open_in_editor(&order_file_path.to_string_lossy())
 }
 
-mod player {
-    use std::path::Path;
-    use std::process::Command;
-
-    use crate::errors::{Result, UpNextError};
+// TODO when testing also test ignore casing
+#[allow(clippy::needless_for_each)]
+pub(super) fn find_series(search_term: &str, all: bool) -> Result<()> {
+    let series_list = load_series_list()?;
+    let mut found_series = series_list.search(search_term);
+    if !all {
+        found_series.retain(|s| !s.is_hidden());
+    }
+    if output::is_json() {
+        let series = found_series
+            .iter()
+            .map(|s| utils::series_json(&series_list, &s.path))
+            .collect::<Result<Vec<_>>>()?;
+        return output::print_json(&json!({ "series": series }));
+    }
+    if found_series.is_empty() {
+        println!("No series found with the search term: {search_term}");
+    } else {
+        found_series.iter().for_each(|s| println!("{s}"));
+    }
+    Ok(())
+}
 
-    pub(super) fn play_in_vlc(file_path: &Path) -> Result<()> {
+/// Print the played episodes oldest first, of all series or of the one chosen with `selector`,
+/// optionally only the ones played `since`.
+pub(super) fn print_history(selector: Option<&str>, since: Option<&str>) -> Result<()> {
+    let series_path = match selector {
+        Some(_) => Some(utils::series_path(&load_series_list()?, selector)?),
+        None => None,
+    };
+    let since = since
+        .map(|since| history::parse_since(since, chrono::Local::now()))
+        .transpose()?;
+    let entries: Vec<HistoryEntry> = history::read()?
+        .into_iter()
+        .filter(|entry| {
+            series_path
+                .as_ref()
+                .is_none_or(|path| entry.series == *path)
+        })
+        .filter(|entry| since.is_none_or(|since| entry.started >= since))
+        .collect();
+    if output::is_json() {
+        return output::print_json(&json!({ "history": entries }));
+    }
+    if entries.is_empty() {
+        return Ok(println!("No episodes played."));
+    }
+    for entry in &entries {
+        let result = match (entry.completed, entry.position_seconds) {
+            (true, _) => "watched".to_string(),
+            (false, Some(position)) => {
+                format!("stopped at {}", crate::utils::format_seconds(position))
+            }
+            (false, None) => "stopped".to_string(),
+        };
         println!(
-            "Starting episode \"{}\" at {}.\n",
-            file_path.file_name().unwrap().to_string_lossy(),
-            chrono::Local::now().format("%H:%M")
+            "{}  {:>7}  {}: \"{}\" ({result})",
+            entry.started.format("%Y-%m-%d %H:%M"),
+            crate::utils::format_seconds(entry.session_seconds()),
+            entry.name,
+            entry.file
         );
+    }
+    Ok(())
+}
 
-        #[cfg(target_os = "linux")]
-        {
-            let mut flatpak_command = std::process::Command::new("flatpak");
-            let flatpak_vlc_command = flatpak_command.arg("run").arg("org.videolan.VLC");
+pub(super) fn print_stats(since: Option<&str>) -> Result<()> {
+    let series_list = load_series_list()?;
+    let now = chrono::Local::now();
+    let since = since
+        .map(|since| history::parse_since(since, now))
+        .transpose()?;
+    let entries: Vec<HistoryEntry> = history::read()?
+        .into_iter()
+        .filter(|entry| since.is_none_or(|since| entry.started >= since))
+        .collect();
+    let stats = stats::compute(&entries, &series_list, now);
+    if output::is_json() {
+        return output::print_json(&json!({ "stats": stats }));
+    }
+    if entries.is_empty() {
+        return Ok(println!("No episodes played."));
+    }
+    Ok(stats::print(&stats))
+}
+
+pub(super) fn print_toml_path() -> Result<()> {
+    let toml_path = crate::utils::get_toml_path()?;
+    if output::is_json() {
+        return output::print_json(&json!({ "toml_path": toml_path }));
+    }
+    Ok(println!("{toml_path}"))
+}
 
-            let res = play_in_vlc_helper(flatpak_vlc_command, file_path);
+pub(super) fn play_next_episode(selector: Option<&str>, overrides: Settings) -> Result<()> {
+    let series_path = utils::series_path(&load_series_list()?, selector)?;
+    play_next_episode_of(&series_path, overrides)
+}
 
-            match res {
-                Err(UpNextError::VlcCommandNotFoundError) => {
-                    eprintln!("VLC flatpak command not found. Trying to run VLC directly.\n");
-                    let mut vlc_command = std::process::Command::new("vlc");
-                    play_in_vlc_helper(&mut vlc_command, file_path)
-                }
-                other => other,
-            }
+fn play_next_episode_of(series_path: &str, overrides: Settings) -> Result<()> {
+    let mut series_list = load_series_list()?;
+    let series = series_list.find_series(series_path)?;
+    let settings = utils::effective_settings(&series_list, series, overrides)?;
+    let player = player::from_config(&settings)?;
+    let threshold = settings.watched_threshold();
+    let series = series_list.find_series_mut(series_path)?;
+    let files = find_files(series, &settings)?;
+    // The series as in the file, to keep what other upnext processes change in the meantime
+    let loaded = series.clone();
+    tracking::resync(series, &files, settings.sort(), true)?;
+    note!("{series}");
+    let description = format!("`next` on \"{}\"", series.title());
+
+    if series.next_episode > i64::try_from(files.len())? {
+        let series_list = utils::reload_with_progress(&loaded, series, &files)?;
+        utils::save_series_list_with_undo(&series_list, description)?;
+        if output::is_json() {
+            let series = utils::series_json(&series_list, series_path)?;
+            return output::print_json(&json!({ "series": series, "played": null }));
         }
-        #[cfg(target_os = "macos")]
-        {
-            let mut vlc_command =
-                std::process::Command::new("/Applications/VLC.app/Contents/MacOS/VLC");
-            play_in_vlc_helper(&mut vlc_command, file_path)
+        Ok(utils::print_completed(
+            series_list.find_series(series_path)?,
+            files.len(),
+        ))
+    } else {
+        let index = usize::try_from(series.next_episode)? - 1;
+        utils::warn_on_episode_number_mismatch(&files, index)?;
+        let file_path = &files[index];
+        let watched = play_and_track(&*player, &threshold, series, &files, index)?;
+        let played = json!({
+            "file": discovery::relative_name(series, file_path),
+            "watched": watched,
+            "position_seconds": series.position_seconds,
+        });
+        let series_list = utils::reload_with_progress(&loaded, series, &files)?;
+        utils::save_series_list_with_undo(&series_list, description)?;
+
+        if output::is_json() {
+            let series = utils::series_json(&series_list, series_path)?;
+            return output::print_json(&json!({ "series": series, "played": played }));
+        }
+        let series = series_list.find_series(series_path)?;
+        println!("{series}");
+        if series.status() == SeriesStatus::Completed {
+            utils::print_completed(series, files.len());
         }
+        Ok(())
     }
+}
 
-    fn play_in_vlc_helper(vlc_command: &mut Command, file_path: &Path) -> Result<()> {
-        let output = vlc_command
-            .arg(file_path)
-            .arg("--play-and-exit")
-            .arg("--fullscreen")
-            .output();
-        match output {
-            Ok(output) => {
-                if output.status.success() {
-                    Ok(())
-                } else {
-                    Err(UpNextError::VlcError(format!(
-                        "exited with status: {}",
-                        output.status
-                    )))
-                }
-            }
-            Err(e) if e.to_string().contains("No such file or directory") => {
-                Err(UpNextError::VlcCommandNotFoundError)
-            }
-            Err(e) => Err(UpNextError::VlcError(e.to_string())),
+pub(super) fn play(selector: Option<&str>, overrides: Settings) -> Result<()> {
+    let mut series_list = load_series_list()?;
+    let series_path = utils::series_path(&series_list, selector)?;
+    let series = series_list.find_series(&series_path)?;
+    let settings = utils::effective_settings(&series_list, series, overrides)?;
+    let player = player::from_config(&settings)?;
+    let threshold = settings.watched_threshold();
+    let series = series_list.find_series_mut(&series_path)?;
+    let files = find_files(series, &settings)?;
+    // The series as in the file, to keep what other upnext processes change in the meantime
+    let mut loaded = series.clone();
+    tracking::resync(series, &files, settings.sort(), true)?;
+
+    println!("{series}");
+    let description = format!("`play` on \"{}\"", series.title());
+    // The episodes are tracked in this copy, the list is loaded again before each save
+    let mut series = series.clone();
+    let mut first_episode = true;
+    while series.next_episode <= i64::try_from(files.len())? {
+        let index = usize::try_from(series.next_episode)? - 1;
+        if first_episode {
+            utils::warn_on_episode_number_mismatch(&files, index)?;
+            first_episode = false;
+        } else {
+            player.countdown_to_next_episode(settings.delay_seconds());
+        }
+        let finished = play_and_track(&*player, &threshold, &mut series, &files, index)?;
+        series_list = utils::reload_with_progress(&loaded, &series, &files)?;
+        utils::save_series_list_with_undo(&series_list, description.clone())?;
+        utils::unlock_series_list();
+        // Continue from the saved next episode, which another upnext process may have changed
+        series = series_list.find_series(&series_path)?.clone();
+        loaded = series.clone();
+        println!("{series}");
+        if !finished {
+            return Ok(());
         }
     }
 
-    pub(super) fn countdown_to_next_episode(episode_delay_seconds: u64) {
-        println!("Playing next episode in {episode_delay_seconds} seconds...");
-        for i in (0..episode_delay_seconds).rev() {
-            std::thread::sleep(std::time::Duration::from_secs(1));
-            println!("{i}");
+    let series_list = utils::reload_with_progress(&loaded, &series, &files)?;
+    utils::save_series_list_with_undo(&series_list, description)?;
+    Ok(utils::print_completed(
+        series_list.find_series(&series_path)?,
+        files.len(),
+    ))
+}
+
+/// Play the episode at `index`, record the progress in `series` and add it to the history.
+/// Returns whether the episode was counted as watched.
+fn play_and_track(
+    player: &dyn Player,
+    threshold: &WatchedThreshold,
+    series: &mut Series,
+    files: &[PathBuf],
+    index: usize,
+) -> Result<bool> {
+    let file_path = &files[index];
+    // Other upnext processes may change the toml file while the episode plays, the caller
+    // loads it again before saving
+    utils::unlock_series_list();
+    let started = chrono::Local::now();
+    let result = player.play_episode(file_path, &PlaybackStart::from(&*series));
+    let ended = chrono::Local::now();
+    // The session is in the history even if the player failed, as not completed
+    let playback = result.as_ref().ok();
+    let entry = HistoryEntry {
+        series: series.path.clone(),
+        name: series.title().to_string(),
+        file: discovery::relative_name(series, file_path),
+        started,
+        ended,
+        completed: playback.is_some_and(|playback| playback.is_watched(threshold)),
+        position_seconds: playback.and_then(|playback| playback.position),
+        duration_seconds: playback.and_then(|playback| playback.duration),
+    };
+    // Losing the history entry is not worth losing the progress
+    if let Err(e) = history::append(&entry) {
+        eprintln!("Could not add the episode to the history: {e}");
+    }
+    let playback = result?;
+    series.last_watched = Some(ended);
+    // Playing a paused or dropped series picks it up again
+    series.status = None;
+    if playback.audio_track.is_some() {
+        series.audio_track.clone_from(&playback.audio_track);
+    }
+    if playback.subtitle_track.is_some() {
+        series.subtitle_track.clone_from(&playback.subtitle_track);
+    }
+    if playback.is_watched(threshold) {
+        series.next_episode += 1;
+        series.position_seconds = None;
+        tracking::record_watched(series, files, index);
+        Ok(true)
+    } else {
+        series.position_seconds = playback.position;
+        if let (Some(position), Some(duration)) = (playback.position, playback.duration) {
+            note!(
+                "Episode not counted as watched: stopped at {} of {}, but {}. \
+                 Playback will resume from there next time.\n",
+                crate::utils::format_seconds(position),
+                crate::utils::format_seconds(duration),
+                threshold
+            );
         }
+        Ok(false)
     }
 }
 
+pub(crate) mod discovery;
+mod listing;
+pub(crate) mod naming;
+pub(crate) mod player;
+mod stats;
+mod tracking;
+
 mod utils {
     use std::io::BufRead;
     use std::path::PathBuf;
+    use std::sync::{Mutex, PoisonError};
+
+    use serde_json::json;
 
+    use super::discovery::{find_files, position_in_season};
+    use super::listing::{self, SeriesJson};
+    use super::naming::EpisodeName;
+    use super::tracking;
     use crate::errors::{Result, UpNextError};
+    use crate::journal;
+    use crate::output::{self, note};
     use crate::persistence;
-    use crate::schema::SeriesList;
+    use crate::schema::{Series, SeriesList, Settings};
     use crate::utils;
 
+    /// The lock of the toml file. It is taken by the first load or save and held until the
+    /// process exits, so that the whole read-modify-write of a command is safe from other
+    /// upnext processes. Waiting for the player or the user releases it with
+    /// `unlock_series_list`.
+    static LOCK: Mutex<Option<std::fs::File>> = Mutex::new(None);
+
+    pub(super) fn lock_series_list() -> Result<()> {
+        let mut lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        if lock.is_none() {
+            *lock = Some(persistence::lock(utils::get_toml_path()?, || {
+                note!("Waiting for another {} to finish...", crate::APP_NAME);
+            })?);
+        }
+        Ok(())
+    }
+
+    /// Let other upnext processes change the toml file. The series list must be loaded again
+    /// before it is saved.
+    pub(super) fn unlock_series_list() {
+        LOCK.lock().unwrap_or_else(PoisonError::into_inner).take();
+    }
+
     pub(super) fn save_series_list(series_list: &SeriesList) -> Result<()> {
+        lock_series_list()?;
         persistence::write_toml_file(utils::get_toml_path()?, series_list)
     }
 
     pub(super) fn load_series_list() -> Result<SeriesList> {
+        lock_series_list()?;
         persistence::read_toml_file(utils::get_toml_path()?)
     }
 
+    /// Load the series list again and merge the progress from `loaded` to `played` into it, so
+    /// that the changes made by other upnext processes while the lock was released are kept.
+    /// `files` are the episodes of the series.
+    pub(super) fn reload_with_progress(
+        loaded: &Series,
+        played: &Series,
+        files: &[PathBuf],
+    ) -> Result<SeriesList> {
+        let mut series_list = load_series_list()?;
+        let series = series_list.find_series_mut(&played.path)?;
+        tracking::merge_progress(series, loaded, played);
+        tracking::update_completion(series, files.len());
+        Ok(series_list)
+    }
+
+    /// Save the series list and add the change to the journal, so that `undo` can restore the
+    /// series as they were before. `description` names the command and the series.
+    pub(super) fn save_series_list_with_undo(
+        series_list: &SeriesList,
+        description: String,
+    ) -> Result<()> {
+        lock_series_list()?;
+        let toml_path = utils::get_toml_path()?;
+        let before = match persistence::read_toml_file(&toml_path) {
+            Ok(before) => before.series,
+            Err(UpNextError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let change = journal::Change::new(description, &before, &series_list.series, |path| {
+            persistence::series_table_text(&toml_path, path)
+        })?;
+        save_series_list(series_list)?;
+        if change.is_empty() {
+            return Ok(());
+        }
+        // Not being able to undo is not worth losing the progress
+        if let Err(e) = journal::record(change) {
+            eprintln!("Could not add the change to the undo journal: {e}");
+        }
+        Ok(())
+    }
+
+    /// Set the series of the change back to how they were before it if `undo`, otherwise to
+    /// how they were after it. A removed series gets its toml table back with the comments.
+    pub(super) fn restore(change: &journal::Change, undo: bool) -> Result<()> {
+        let toml_path = utils::get_toml_path()?;
+        let mut series_list = match load_series_list() {
+            Ok(series_list) => series_list,
+            Err(UpNextError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                SeriesList::new()
+            }
+            Err(e) => return Err(e),
+        };
+        for series_change in &change.series {
+            let (from, to) = if undo {
+                (&series_change.after, &series_change.before)
+            } else {
+                (&series_change.before, &series_change.after)
+            };
+            let Some(state) = to else {
+                series_list.remove_series(&series_change.path);
+                continue;
+            };
+            if let Ok(series) = series_list.find_series_mut(&series_change.path) {
+                // Only what the command changed, the series may have been edited since
+                match from {
+                    Some(from) => tracking::change_progress(series, from, state),
+                    None => *series = state.clone(),
+                }
+                continue;
+            }
+            let index = series_change.index.min(series_list.series.len());
+            if let Some(table) = &series_change.table {
+                persistence::restore_series_table(&toml_path, index, table)?;
+            }
+            series_list.series.insert(index, state.clone());
+        }
+        save_series_list(&series_list)?;
+        let done = if undo { "Undid" } else { "Redid" };
+        println!("{done} {}.", change.description);
+        for series_change in &change.series {
+            if let Ok(series) = series_list.find_series(&series_change.path) {
+                println!("{series}");
+            }
+        }
+        Ok(())
+    }
+
     pub(super) fn get_cwd() -> Result<String> {
         std::env::current_dir()?
             .to_str()
@@ -241,50 +811,194 @@ mod utils {
             .map(ToString::to_string)
     }
 
-    pub(super) fn find_files(path: &str) -> Result<Vec<PathBuf>> {
-        let extensions = [
-            "mkv", "mp4", "avi", "flv", "mov", "wmv", "webm", "mpg", "mpeg", "m4v",
+    /// Tell that all episodes of the series were watched, or that it has none.
+    pub(super) fn print_completed(series: &Series, total: usize) {
+        if total == 0 {
+            println!(
+                "No episodes found in {}, `{} files` shows why.",
+                series.path,
+                crate::APP_NAME
+            );
+        } else {
+            println!(
+                "\"{}\" is completed, all {total} episodes are watched. Run `{} set` to watch it again.",
+                series.title(),
+                crate::APP_NAME
+            );
+        }
+    }
+
+    /// The series at `series_path` as it is shown in JSON documents.
+    pub(super) fn series_json<'a>(
+        series_list: &'a SeriesList,
+        series_path: &str,
+    ) -> Result<SeriesJson<'a>> {
+        let index = series_list.find_series_index(series_path)?;
+        Ok(listing::row(series_list, index).to_json())
+    }
+
+    /// Print the series after `next_episode` was changed by hand.
+    pub(super) fn print_changed_series(
+        series_list: &SeriesList,
+        series_path: &str,
+        previous_next_episode: i64,
+    ) -> Result<()> {
+        if output::is_json() {
+            let series = series_json(series_list, series_path)?;
+            return output::print_json(&json!({
+                "series": series,
+                "previous_next_episode": previous_next_episode,
+            }));
+        }
+        let series = series_list.find_series(series_path)?;
+        Ok(println!("{series}"))
+    }
+
+    /// Path of the series chosen with `--series`, or else the current directory.
+    ///
+    /// The selector is tried as `#<index>` (starting at 1, in the order of the toml file), as
+    /// the path of a series, as the name, directory name or an alias of a series and finally as
+    /// a part of the path, name or an alias, all ignoring case. A selector matching several series is refused.
+    pub(super) fn series_path(series_list: &SeriesList, selector: Option<&str>) -> Result<String> {
+        let Some(selector) = selector else {
+            return get_cwd();
+        };
+        let unknown = || UpNextError::UnknownSeries(selector.to_string());
+        if let Some(index) = selector.strip_prefix('#') {
+            let index = index.parse::<usize>().map_err(|_| unknown())?;
+            let series = index
+                .checked_sub(1)
+                .and_then(|i| series_list.series.get(i))
+                .ok_or_else(unknown)?;
+            return Ok(series.path.clone());
+        }
+        let as_path = [
+            std::path::absolute(selector),
+            std::fs::canonicalize(selector),
         ];
-        let mut files = vec![];
-        // read all files in the directory
-        for entry in std::fs::read_dir(path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                let ext = path
-                    .extension()
-                    .ok_or(UpNextError::GenericError(format!(
-                        "Path contained no extension: {}",
-                        path.to_str().unwrap()
-                    )))?
-                    .to_str()
-                    .expect("Could not convert extension to string.");
-                {
-                    if extensions.contains(&ext) {
-                        files.push(path);
-                    }
+        if let Some(path) = as_path
+            .into_iter()
+            .flatten()
+            .filter_map(|path| path.to_str().map(ToString::to_string))
+            .find(|path| series_list.contains_path(path))
+        {
+            return Ok(path);
+        }
+        let by_name: Vec<&Series> = series_list
+            .series
+            .iter()
+            .filter(|s| s.is_called(selector))
+            .collect();
+        let matches = match by_name[..] {
+            [] => series_list.search(selector),
+            _ => by_name,
+        };
+        match matches[..] {
+            [] => Err(unknown()),
+            [series] => Ok(series.path.clone()),
+            _ => Err(UpNextError::AmbiguousSeries(
+                selector.to_string(),
+                matches
+                    .iter()
+                    .map(|s| format!("{} ({})", s.title(), s.path))
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Settings from the toml file, overridden by the settings of the series, environment
+    /// variables and then by `overrides` from the command line.
+    pub(super) fn effective_settings(
+        series_list: &SeriesList,
+        series: &Series,
+        overrides: Settings,
+    ) -> Result<Settings> {
+        Ok(series_list
+            .settings
+            .clone()
+            .for_series(&series.settings)
+            .overridden_by(utils::settings_from_env()?)
+            .overridden_by(overrides))
+    }
+
+    /// The episode files of the series in `path`, `None` if they can not be listed.
+    pub(super) fn find_series_files(series_list: &SeriesList, path: &str) -> Option<Vec<PathBuf>> {
+        let series = series_list.find_series(path).ok()?;
+        let settings = effective_settings(series_list, series, Settings::default()).ok()?;
+        find_files(series, &settings).ok()
+    }
+
+    /// Ask before playing the file at `index` if the episode number in its name does not match
+    /// its position in the directory, which means that `next_episode` is probably off.
+    pub(super) fn warn_on_episode_number_mismatch(files: &[PathBuf], index: usize) -> Result<()> {
+        let file_name = files[index]
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let Some(name) = EpisodeName::parse(&file_name) else {
+            return Ok(());
+        };
+        let episode_number = position_in_season(files, index);
+        if name.is_special() || name.contains(episode_number) {
+            return Ok(());
+        }
+        if confirm(&format!("Warning: The file \"{file_name}\" is {name} by its name, but episode {episode_number} of its season in the directory. If you deleted some episodes the `next_episode` which is an offset in the directory video files may need to be udpated. Play it anyway?"))? {
+            Ok(())
+        } else {
+            Err(UpNextError::WrongEpisodeNumber)
+        }
+    }
+
+    /// Open the file in `$VISUAL` or `$EDITOR` and wait for it to close, or else in the default
+    /// application. The lock of the toml file is released while the editor is open, so the
+    /// series list must be loaded again before it is saved.
+    pub(super) fn open_in_editor(path: &str) -> Result<()> {
+        unlock_series_list();
+        let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR"));
+        match editor {
+            Ok(editor) if !editor.trim().is_empty() => {
+                let mut words = editor.split_whitespace();
+                let program = words.next().expect("checked that it is not empty");
+                let status = std::process::Command::new(program)
+                    .args(words)
+                    .arg(path)
+                    .status()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(UpNextError::GenericError(format!(
+                        "Editor `{editor}` exited with {status}"
+                    )))
                 }
             }
+            _ => {
+                println!("Opening {path} in the default application.");
+                let _output = std::process::Command::new("xdg-open").arg(path).output()?;
+                Ok(())
+            }
         }
-        files.sort();
-        Ok(files)
     }
 
-    pub(super) fn warn_on_episode_number_mismatch(
-        path: &PathBuf,
-        episode_number: i64,
-    ) -> Result<()> {
-        let file_name: &str = path.file_name().unwrap().to_str().unwrap();
-        if !file_name.contains(&episode_number.to_string()) {
-            println!("Warning: The file \"{}\" does not contain the episode number {}. If you deleted some episodes the `next_episode` which is an offset in the directory video files may need to be udpated. Play it anyway?", file_name, episode_number);
-            let response = std::io::stdin().lock().lines().next().unwrap()?;
-            match response.to_lowercase() {
-                answer if answer.contains("n") => Err(UpNextError::WrongEpisodeNumber),
-                answer if answer.contains("y") || answer.is_empty() => Ok(()),
-                _ => Err(UpNextError::WrongEpisodeNumber),
-            }
-        } else {
-            Ok(())
+    /// Print the question and read the trimmed answer from stdin, `None` if there is no input.
+    /// The lock of the toml file is released while waiting, so the series list must be loaded
+    /// again before it is saved.
+    pub(super) fn ask(question: &str) -> Result<Option<String>> {
+        unlock_series_list();
+        note!("{question}");
+        match std::io::stdin().lock().lines().next() {
+            Some(line) => Ok(Some(line?.trim().to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Print the question and read a yes/no answer from stdin. An empty answer or no input
+    /// at all means yes.
+    pub(super) fn confirm(question: &str) -> Result<bool> {
+        let response = ask(question)?.unwrap_or_default();
+        match response.to_lowercase() {
+            answer if answer.contains("n") => Ok(false),
+            answer if answer.contains("y") || answer.is_empty() => Ok(true),
+            _ => Ok(false),
         }
     }
 }
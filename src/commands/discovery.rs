@@ -0,0 +1,485 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use super::naming::EpisodeName;
+use crate::errors::{Result, UpNextError};
+use crate::schema::{Series, Settings, SortOrder};
+
+/// Name of the file in the series directory with the manual episode order.
+pub(super) const ORDER_FILE_NAME: &str = ".upnext-order";
+/// Name of the file in the series directory with gitignore-style patterns of files which are
+/// not episodes.
+pub(super) const IGNORE_FILE_NAME: &str = ".upnextignore";
+
+/// The episode files of a series in playing order, see [`SortOrder`].
+///
+/// With the natural order files in a directory are ordered by the season and episode in their
+/// names, see [`EpisodeName`], files without them come after that and specials (season 0) last.
+///
+/// With the `recursive` setting subdirectories are searched too. The files directly in `path`
+/// come first, then the season directories by their season number, then other directories by
+/// name and finally the specials.
+pub(super) fn find_files(series: &Series, settings: &Settings) -> Result<Vec<PathBuf>> {
+    Ok(scan(series, settings)?.episodes)
+}
+
+/// The episodes of a series and the other entries in its directory which were ignored.
+#[derive(Debug, Default)]
+pub(super) struct Scan {
+    /// Episode files in playing order.
+    pub(super) episodes: Vec<PathBuf>,
+    /// Everything else with the reason why it is not an episode, in directory order.
+    pub(super) ignored: Vec<(PathBuf, Ignored)>,
+}
+
+/// Look through the series directory for episodes, see [`find_files`].
+pub(super) fn scan(series: &Series, settings: &Settings) -> Result<Scan> {
+    let options = Options {
+        extensions: settings.extensions(),
+        recursive: settings.recursive(),
+        sort: settings.sort(),
+        ignore: ignore_rules(series, settings)?,
+    };
+    let mut scan = Scan::default();
+    collect_files(Path::new(&series.path), &options, &mut scan)?;
+    if options.sort == SortOrder::Manual {
+        let order = manual_order(series)?.ok_or_else(|| {
+            UpNextError::ConfigError(format!(
+                "The series is sorted manually but has no episode order, run `{} order regenerate` to create one",
+                crate::APP_NAME
+            ))
+        })?;
+        scan.episodes = apply_order(series, scan.episodes, &order);
+    }
+    Ok(scan)
+}
+
+/// The manual episode order of the series: its `order` list, or else the lines of the
+/// `.upnext-order` file in its directory without empty lines and `#` comments.
+pub(super) fn manual_order(series: &Series) -> Result<Option<Vec<String>>> {
+    if let Some(order) = &series.settings.order {
+        return Ok(Some(order.clone()));
+    }
+    match std::fs::read_to_string(order_file_path(series)) {
+        Ok(content) => Ok(Some(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(ToString::to_string)
+                .collect(),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(super) fn order_file_path(series: &Series) -> PathBuf {
+    Path::new(&series.path).join(ORDER_FILE_NAME)
+}
+
+/// Write the files to the `.upnext-order` file of the series.
+pub(super) fn write_order_file(series: &Series, files: &[PathBuf]) -> Result<()> {
+    let mut content = format!(
+        "# Episode order for {}, one file per line relative to this directory.\n\
+         # Files which are not listed are played after the listed ones.\n",
+        crate::APP_NAME
+    );
+    for file_path in files {
+        content.push_str(&relative_name(series, file_path));
+        content.push('\n');
+    }
+    Ok(std::fs::write(order_file_path(series), content)?)
+}
+
+/// The files in the manual `order`. Files which are not in it are played after them in their
+/// sorted order, entries without a file are skipped.
+fn apply_order(series: &Series, mut files: Vec<PathBuf>, order: &[String]) -> Vec<PathBuf> {
+    let mut ordered = vec![];
+    for name in order {
+        let path = Path::new(&series.path).join(name);
+        if let Some(i) = files.iter().position(|file_path| *file_path == path) {
+            ordered.push(files.remove(i));
+        }
+    }
+    ordered.append(&mut files);
+    ordered
+}
+
+/// The path of the episode relative to the series directory, for a recursive series this
+/// includes the season directory.
+pub(super) fn relative_name(series: &Series, path: &Path) -> String {
+    path.strip_prefix(&series.path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The index in `files` where the episode `name`, relative to the series directory, would be
+/// if it was still there: the first file sorted after it by `sort`.
+pub(super) fn position_of_missing(
+    series: &Series,
+    files: &[PathBuf],
+    name: &str,
+    sort: SortOrder,
+) -> usize {
+    if sort == SortOrder::Manual {
+        // The first of the episodes listed after it, if it is still in the episode order
+        if let Ok(Some(order)) = manual_order(series) {
+            if let Some(i) = order.iter().position(|listed| listed == name) {
+                let later = &order[i + 1..];
+                return files
+                    .iter()
+                    .position(|file_path| later.contains(&relative_name(series, file_path)))
+                    .unwrap_or(files.len());
+            }
+        }
+    }
+    files
+        .iter()
+        .position(|file_path| {
+            compare_relative_names(sort, &relative_name(series, file_path), name)
+                == Ordering::Greater
+        })
+        .unwrap_or(files.len())
+}
+
+/// Compare names relative to the series directory the way [`collect_files`] orders them. The
+/// modification time of a missing file is unknown, so with [`SortOrder::Mtime`] the natural
+/// order is used.
+fn compare_relative_names(sort: SortOrder, a: &str, b: &str) -> Ordering {
+    let parts = |name: &str| -> Vec<String> {
+        Path::new(name)
+            .iter()
+            .map(|part| part.to_string_lossy().to_string())
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+    for (i, (a_part, b_part)) in a.iter().zip(&b).enumerate() {
+        let ordering = match (i + 1 < a.len(), i + 1 < b.len()) {
+            // The files in a directory come before its subdirectories
+            (false, true) => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            _ if sort == SortOrder::Lexical => a_part.cmp(b_part),
+            (true, true) => season_sort_key(a_part).cmp(&season_sort_key(b_part)),
+            (false, false) => episode_sort_key(a_part).cmp(&episode_sort_key(b_part)),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+struct Options {
+    extensions: Vec<String>,
+    recursive: bool,
+    sort: SortOrder,
+    ignore: Gitignore,
+}
+
+/// The `ignore` patterns from the settings followed by the ones in the `.upnextignore` file, so
+/// that the file can re-include files with `!pattern`.
+fn ignore_rules(series: &Series, settings: &Settings) -> Result<Gitignore> {
+    let invalid =
+        |e: ignore::Error| UpNextError::ConfigError(format!("Invalid ignore pattern: {e}"));
+    let mut builder = GitignoreBuilder::new(&series.path);
+    for pattern in settings.ignore() {
+        builder.add_line(None, &pattern).map_err(invalid)?;
+    }
+    let ignore_file_path = Path::new(&series.path).join(IGNORE_FILE_NAME);
+    if ignore_file_path.is_file() {
+        if let Some(e) = builder.add(ignore_file_path) {
+            return Err(invalid(e));
+        }
+    }
+    builder.build().map_err(invalid)
+}
+
+fn collect_files(dir: &Path, options: &Options, scan: &mut Scan) -> Result<()> {
+    let mut dir_files = vec![];
+    let mut subdirs = vec![];
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        // Hidden files such as the `.upnext-order` file are never episodes
+        if is_hidden(&path) {
+            scan.ignored.push((path, Ignored::Hidden));
+            continue;
+        }
+        let metadata = path.symlink_metadata()?;
+        if let ignore::Match::Ignore(glob) = options.ignore.matched(&path, metadata.is_dir()) {
+            let pattern = Ignored::Pattern {
+                pattern: glob.original().to_string(),
+                in_ignore_file: glob.from().is_some(),
+            };
+            scan.ignored.push((path, pattern));
+            continue;
+        }
+        match FileKind::of(&path, metadata.is_dir(), &options.extensions) {
+            _ if metadata.is_symlink() && path.is_dir() => {
+                scan.ignored.push((path, Ignored::SymlinkedDirectory));
+            }
+            FileKind::Video => dir_files.push(path),
+            FileKind::Directory if options.recursive => subdirs.push(path),
+            FileKind::Directory => scan.ignored.push((path, Ignored::Directory)),
+            kind => scan.ignored.push((path, Ignored::Kind(kind))),
+        }
+    }
+    match options.sort {
+        SortOrder::Natural | SortOrder::Manual => {
+            dir_files.sort_by_cached_key(|file| episode_sort_key(&file_name(file)));
+            subdirs.sort_by_cached_key(|dir| season_sort_key(&file_name(dir)));
+        }
+        SortOrder::Lexical => {
+            dir_files.sort();
+            subdirs.sort();
+        }
+        SortOrder::Mtime => {
+            dir_files.sort_by_cached_key(|file| (modified(file), file.clone()));
+            subdirs.sort_by_cached_key(|dir| (modified(dir), dir.clone()));
+        }
+    }
+    scan.episodes.append(&mut dir_files);
+    for subdir in subdirs {
+        collect_files(&subdir, options, scan)?;
+    }
+    Ok(())
+}
+
+pub(crate) const SUBTITLE_EXTENSIONS: [&str; 5] = ["srt", "ass", "ssa", "vtt", "sub"];
+const IMAGE_EXTENSIONS: [&str; 7] = ["jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff"];
+
+/// What an entry in a series directory is, judged by its extension.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum FileKind {
+    /// One of the video extensions from the settings.
+    Video,
+    Subtitle,
+    Image,
+    Directory,
+    /// A file without an extension, like `README`.
+    NoExtension,
+    /// Any other file, with its extension.
+    Unknown(String),
+}
+
+impl FileKind {
+    fn of(path: &Path, is_dir: bool, video_extensions: &[String]) -> Self {
+        if is_dir {
+            return FileKind::Directory;
+        }
+        // Extensions which are not valid UTF-8 can still be compared after a lossy conversion
+        let Some(extension) = path.extension().map(|e| e.to_string_lossy().to_lowercase()) else {
+            return FileKind::NoExtension;
+        };
+        let is_one_of = |extensions: &[&str]| extensions.contains(&extension.as_str());
+        if video_extensions
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(&extension))
+        {
+            FileKind::Video
+        } else if is_one_of(&SUBTITLE_EXTENSIONS) {
+            FileKind::Subtitle
+        } else if is_one_of(&IMAGE_EXTENSIONS) {
+            FileKind::Image
+        } else {
+            FileKind::Unknown(extension)
+        }
+    }
+}
+
+impl Display for FileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileKind::Video => write!(f, "video"),
+            FileKind::Subtitle => write!(f, "subtitle"),
+            FileKind::Image => write!(f, "image"),
+            FileKind::Directory => write!(f, "directory"),
+            FileKind::NoExtension => write!(f, "no extension"),
+            FileKind::Unknown(extension) => write!(f, "unknown extension .{extension}"),
+        }
+    }
+}
+
+/// Why an entry in the series directory is not an episode.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Ignored {
+    Hidden,
+    /// Only directories of a recursive series are searched.
+    Directory,
+    /// Symlinked directories are not followed, they could form a cycle.
+    SymlinkedDirectory,
+    Kind(FileKind),
+    /// Matches an ignore pattern from the settings or from the `.upnextignore` file.
+    Pattern {
+        pattern: String,
+        in_ignore_file: bool,
+    },
+}
+
+impl Display for Ignored {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ignored::Hidden => write!(f, "hidden"),
+            Ignored::Directory => write!(f, "directory, the series is not recursive"),
+            Ignored::SymlinkedDirectory => write!(f, "symlinked directory, not followed"),
+            Ignored::Kind(kind) => write!(f, "{kind}"),
+            Ignored::Pattern {
+                pattern,
+                in_ignore_file,
+            } => {
+                let source = if *in_ignore_file {
+                    IGNORE_FILE_NAME
+                } else {
+                    "the settings"
+                };
+                write!(f, "ignored by `{pattern}` in {source}")
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn is_hidden(path: &Path) -> bool {
+    file_name(path).starts_with('.')
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Regular episodes first by season and episode, then files without an episode number, then
+/// the specials. Ties are ordered by name.
+fn episode_sort_key(file_name: &str) -> (u8, u32, u32, NaturalName) {
+    let natural_name = NaturalName(file_name.to_string());
+    match EpisodeName::parse(file_name) {
+        Some(name) if name.is_special() => (2, 0, name.episode, natural_name),
+        Some(name) => (0, name.season.unwrap_or(0), name.episode, natural_name),
+        None => (1, 0, 0, natural_name),
+    }
+}
+
+/// Regular seasons first by number, then other directories by name, then the specials.
+fn season_sort_key(dir_name: &str) -> (u8, u32, NaturalName) {
+    let natural_name = NaturalName(dir_name.to_string());
+    match season_number(dir_name) {
+        Some(0) => (2, 0, natural_name),
+        Some(season) => (0, season, natural_name),
+        None => (1, 0, natural_name),
+    }
+}
+
+/// A name ordered the way people read it: runs of digits are compared by their value and
+/// letters ignoring case, so `Episode 2` comes before `episode 10`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct NaturalName(pub(crate) String);
+
+impl Ord for NaturalName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (mut a, mut b) = (self.0.as_str(), other.0.as_str());
+        while let (Some(a_first), Some(b_first)) = (a.chars().next(), b.chars().next()) {
+            let ordering = if a_first.is_ascii_digit() && b_first.is_ascii_digit() {
+                let (a_number, a_rest) = split_number(a);
+                let (b_number, b_rest) = split_number(b);
+                (a, b) = (a_rest, b_rest);
+                compare_numbers(a_number, b_number)
+            } else {
+                a = &a[a_first.len_utf8()..];
+                b = &b[b_first.len_utf8()..];
+                a_first.to_lowercase().cmp(b_first.to_lowercase())
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        // Names only differing in case or zero padding still need a stable order
+        a.len().cmp(&b.len()).then_with(|| self.0.cmp(&other.0))
+    }
+}
+
+impl PartialOrd for NaturalName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn split_number(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+/// Compare digit strings by value, without parsing them so that long numbers can not overflow.
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// The season number in a directory name like `Season 02`, `season.2`, `Series 3` or `S04`.
+/// Specials are season 0.
+fn season_number(dir_name: &str) -> Option<u32> {
+    let name = dir_name.trim().to_lowercase();
+    if name == "specials" || name == "extras" {
+        return Some(0);
+    }
+    let rest = ["season", "series", "s"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))?
+        .trim_start_matches([' ', '.', '_', '-']);
+    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    let after = &rest[digits.len()..];
+    if digits.is_empty() || after.starts_with(|c: char| c.is_alphanumeric()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// The season and episode of the file at `index`, from its name or else from its season
+/// directory and its position in it.
+pub(super) fn episode_name(files: &[PathBuf], index: usize) -> Option<EpisodeName> {
+    let file_path = files.get(index)?;
+    let dir_season = file_path
+        .parent()
+        .and_then(|dir| season_number(&file_name(dir)));
+    match EpisodeName::parse(&file_name(file_path)) {
+        Some(name) if name.season.is_some() => Some(name),
+        Some(name) => Some(EpisodeName {
+            season: dir_season,
+            ..name
+        }),
+        None => {
+            let episode = position_in_season(files, index);
+            Some(EpisodeName {
+                season: Some(dir_season?),
+                episode,
+                last_episode: episode,
+            })
+        }
+    }
+}
+
+/// The episode number the file at `index` should have: one after the episodes in the files
+/// before it in the same directory and season.
+pub(super) fn position_in_season(files: &[PathBuf], index: usize) -> u32 {
+    let file_path = &files[index];
+    let season = EpisodeName::parse(&file_name(file_path)).and_then(|name| name.season);
+    let episodes_before: u32 = files[..index]
+        .iter()
+        .filter(|file| file.parent() == file_path.parent())
+        .map(|file| EpisodeName::parse(&file_name(file)))
+        .filter(|name| season.is_none() || name.and_then(|name| name.season) == season)
+        .map(|name| name.map_or(1, |name| name.len()))
+        .sum();
+    episodes_before + 1
+}
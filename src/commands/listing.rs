@@ -0,0 +1,244 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::commands::utils::find_series_files;
+use crate::schema::{Series, SeriesList, SeriesStatus};
+
+/// How `list` orders the series, by default they are in the order of the toml file.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub(crate) enum ListSort {
+    /// By name, ignoring case.
+    Name,
+    /// Most watched first, by the share of episodes watched.
+    Progress,
+    /// Most recently watched first.
+    LastWatched,
+}
+
+/// Which series `list` shows.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub(crate) enum ListFilter {
+    /// All but the completed series.
+    Unfinished,
+    #[value(alias = "completed")]
+    Finished,
+    /// Started or not yet started series which are not paused.
+    Watching,
+    Paused,
+    Dropped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Status {
+    NotStarted,
+    Watching,
+    Paused,
+    Completed,
+    Dropped,
+    /// The directory of the series can not be read, e.g. on an unmounted drive.
+    Missing,
+}
+
+impl Status {
+    fn is_shown_by(self, filter: ListFilter) -> bool {
+        match filter {
+            ListFilter::Unfinished => self != Status::Completed,
+            ListFilter::Finished => self == Status::Completed,
+            ListFilter::Watching => matches!(self, Status::NotStarted | Status::Watching),
+            ListFilter::Paused => self == Status::Paused,
+            ListFilter::Dropped => self == Status::Dropped,
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Status::NotStarted => "not started",
+            Status::Watching => "watching",
+            Status::Paused => "paused",
+            Status::Completed => "completed",
+            Status::Dropped => "dropped",
+            Status::Missing => "missing",
+        })
+    }
+}
+
+/// A series in the `list` table.
+pub(super) struct Row<'a> {
+    /// Position in the toml file starting at 1, as used by `--series #<n>`.
+    index: usize,
+    series: &'a Series,
+    /// Watched and total number of episodes, if the files could be listed.
+    progress: Option<(usize, usize)>,
+    status: Status,
+}
+
+impl<'a> Row<'a> {
+    fn percentage(&self) -> Option<usize> {
+        self.progress
+            .filter(|(_, total)| *total > 0)
+            .map(|(watched, total)| watched * 100 / total)
+    }
+
+    fn last_watched(&self) -> Option<DateTime<Local>> {
+        self.series.last_watched
+    }
+
+    pub(super) fn to_json(&self) -> SeriesJson<'a> {
+        SeriesJson {
+            index: self.index,
+            name: self.series.title(),
+            aliases: self.series.aliases.as_deref().unwrap_or_default(),
+            path: &self.series.path,
+            next_episode: self.series.next_episode,
+            watched_episodes: self.progress.map(|(watched, _)| watched),
+            total_episodes: self.progress.map(|(_, total)| total),
+            status: self.status,
+            position_seconds: self.series.position_seconds,
+            last_watched: self.last_watched().map(|time| time.to_rfc3339()),
+        }
+    }
+}
+
+/// A series in the documents printed with `--output json`.
+#[derive(Serialize)]
+pub(super) struct SeriesJson<'a> {
+    index: usize,
+    name: &'a str,
+    aliases: &'a [String],
+    path: &'a str,
+    next_episode: i64,
+    /// `None` if the files of the series can not be listed, same for `total_episodes`.
+    watched_episodes: Option<usize>,
+    total_episodes: Option<usize>,
+    status: Status,
+    position_seconds: Option<u64>,
+    last_watched: Option<String>,
+}
+
+pub(super) fn rows(series_list: &SeriesList) -> Vec<Row<'_>> {
+    (0..series_list.series.len())
+        .map(|i| row(series_list, i))
+        .collect()
+}
+
+/// The row of the series at `index` in the list.
+pub(super) fn row(series_list: &SeriesList, index: usize) -> Row<'_> {
+    let series = &series_list.series[index];
+    let total = find_series_files(series_list, &series.path).map(|files| files.len());
+    let progress = total.map(|total| {
+        let watched = usize::try_from(series.next_episode - 1).unwrap_or(0);
+        (watched.min(total), total)
+    });
+    let status = match (progress, series.status()) {
+        (None, _) => Status::Missing,
+        (_, SeriesStatus::Paused) => Status::Paused,
+        (_, SeriesStatus::Dropped) => Status::Dropped,
+        (Some((watched, total)), _) if watched >= total => Status::Completed,
+        (Some((0, _)), _) if series.position_seconds.is_none() => Status::NotStarted,
+        (Some(_), _) => Status::Watching,
+    };
+    Row {
+        index: index + 1,
+        series,
+        progress,
+        status,
+    }
+}
+
+/// Keep the rows shown by `filter`, without the dropped series unless `all` or filtered for,
+/// and sort them.
+pub(super) fn filter_and_sort(
+    rows: &mut Vec<Row<'_>>,
+    filter: Option<ListFilter>,
+    sort: Option<ListSort>,
+    all: bool,
+) {
+    let with_hidden = all || filter == Some(ListFilter::Dropped);
+    rows.retain(|row| {
+        filter.is_none_or(|filter| row.status.is_shown_by(filter))
+            && (with_hidden || !row.series.is_hidden())
+    });
+    match sort {
+        None => {}
+        Some(ListSort::Name) => rows.sort_by_key(|row| row.series.title().to_lowercase()),
+        Some(ListSort::Progress) => {
+            rows.sort_by_key(|row| std::cmp::Reverse(row.percentage()));
+        }
+        Some(ListSort::LastWatched) => {
+            rows.sort_by_key(|row| std::cmp::Reverse(row.last_watched()));
+        }
+    }
+}
+
+/// Print the rows as a table, with ANSI colours if `colour`.
+pub(super) fn print_table(rows: &[Row<'_>], colour: bool) {
+    let paint = |text: String, code: &str| {
+        if colour {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text
+        }
+    };
+    let fractions: Vec<String> = rows
+        .iter()
+        .map(|row| match row.progress {
+            Some((watched, total)) => format!("{watched}/{total}"),
+            None => "?".to_string(),
+        })
+        .collect();
+    let fraction_width = fractions.iter().map(String::len).max().unwrap_or_default();
+    let progress: Vec<String> = rows
+        .iter()
+        .zip(fractions)
+        .map(|(row, fraction)| {
+            let percentage = match row.percentage() {
+                Some(percentage) => format!("{percentage}%"),
+                None => "-".to_string(),
+            };
+            format!("{fraction:>fraction_width$}  {percentage:>4}")
+        })
+        .collect();
+    let name_width = rows
+        .iter()
+        .map(|row| row.series.title().chars().count())
+        .chain(["Name".len()])
+        .max()
+        .unwrap_or_default();
+    let progress_width = progress
+        .iter()
+        .map(String::len)
+        .chain(["Progress".len()])
+        .max()
+        .unwrap_or_default();
+
+    let header = format!(
+        "{:>4}  {:<name_width$}  {:>progress_width$}  {:<11}  Last watched",
+        "#", "Name", "Progress", "Status"
+    );
+    println!("{}", paint(header, "1"));
+    for (row, progress) in rows.iter().zip(progress) {
+        let status_colour = match row.status {
+            Status::NotStarted => "2",
+            Status::Watching => "33",
+            Status::Paused => "36",
+            Status::Completed => "32",
+            Status::Dropped => "35",
+            Status::Missing => "31",
+        };
+        let last_watched = match row.last_watched() {
+            Some(time) => time.format("%Y-%m-%d").to_string(),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:>4}  {}  {progress:>progress_width$}  {}  {last_watched}",
+            row.index,
+            paint(format!("{:<name_width$}", row.series.title()), "1"),
+            paint(format!("{:<11}", row.status), status_colour),
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt::Display;
+
+/// Season and episode numbers parsed from an episode file name.
+///
+/// Understands the common naming schemes `S01E05`, `s1.e5`, `1x05` and `Episode 5`/`Ep 5`,
+/// including multi-episode files like `S01E01-E02`, `S01E01E02` or `1x01-1x02`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct EpisodeName {
+    /// `None` if the name only has an episode number, `Some(0)` for specials.
+    pub(crate) season: Option<u32>,
+    pub(crate) episode: u32,
+    /// The last episode in a multi-episode file, otherwise equal to `episode`.
+    pub(crate) last_episode: u32,
+}
+
+impl EpisodeName {
+    pub(crate) fn parse(file_name: &str) -> Option<Self> {
+        let name: Vec<char> = file_name.to_lowercase().chars().collect();
+        let starts = 0..name.len();
+        starts
+            .clone()
+            .find_map(|i| parse_season_episode(&name, i))
+            .or_else(|| starts.clone().find_map(|i| parse_cross(&name, i)))
+            .or_else(|| starts.clone().find_map(|i| parse_episode_word(&name, i)))
+    }
+
+    pub(crate) fn is_special(&self) -> bool {
+        self.season == Some(0)
+    }
+
+    /// Whether the file contains the episode with this number.
+    pub(crate) fn contains(&self, episode: u32) -> bool {
+        (self.episode..=self.last_episode).contains(&episode)
+    }
+
+    /// Number of episodes in the file.
+    pub(crate) fn len(&self) -> u32 {
+        self.last_episode - self.episode + 1
+    }
+}
+
+impl Display for EpisodeName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(season) = self.season {
+            write!(f, "S{season:02}")?;
+        }
+        write!(f, "E{:02}", self.episode)?;
+        if self.last_episode != self.episode {
+            write!(f, "-E{:02}", self.last_episode)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads numbers and markers from a lowercase file name.
+struct Cursor<'a> {
+    name: &'a [char],
+    i: usize,
+}
+
+impl Cursor<'_> {
+    fn eat(&mut self, text: &str) -> bool {
+        let end = self.i + text.chars().count();
+        if end <= self.name.len() && self.name[self.i..end].iter().copied().eq(text.chars()) {
+            self.i = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_separator(&mut self) {
+        if matches!(self.peek(), Some(' ' | '.' | '_' | '-')) {
+            self.i += 1;
+        }
+    }
+
+    /// A number of `min..=max` digits which is not followed by another digit.
+    fn number(&mut self, min: usize, max: usize) -> Option<u32> {
+        let digits = self.name[self.i..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits < min || digits > max {
+            return None;
+        }
+        let number: String = self.name[self.i..self.i + digits].iter().collect();
+        self.i += digits;
+        number.parse().ok()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.name.get(self.i).copied()
+    }
+
+    /// Whether the marker ends here, so that `720p` or `x264` are not taken as numbers.
+    fn at_boundary(&self) -> bool {
+        !self.peek().is_some_and(char::is_alphanumeric)
+    }
+
+    /// Run `parse` and only move forward if it succeeds.
+    fn attempt<T>(&mut self, parse: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        let start = self.i;
+        let result = parse(self);
+        if result.is_none() {
+            self.i = start;
+        }
+        result
+    }
+}
+
+/// Whether a marker can start at `i`, i.e. it is not in the middle of a word or number.
+fn starts_word(name: &[char], i: usize) -> bool {
+    i == 0 || !name[i - 1].is_alphanumeric()
+}
+
+/// `S01E05`, `s1.e5`, `S01E01-E02`, `S01E01E02` or `S01E01-02`.
+fn parse_season_episode(name: &[char], i: usize) -> Option<EpisodeName> {
+    if !starts_word(name, i) {
+        return None;
+    }
+    let mut cursor = Cursor { name, i };
+    if !cursor.eat("s") {
+        return None;
+    }
+    let season = cursor.number(1, 4)?;
+    cursor.eat_separator();
+    if !cursor.eat("e") {
+        return None;
+    }
+    let episode = cursor.number(1, 4)?;
+    let last_episode = cursor
+        .attempt(|cursor| {
+            let dash = cursor.eat("-");
+            if !cursor.eat("e") && !dash {
+                return None;
+            }
+            let last = cursor.number(1, 4)?;
+            cursor.at_boundary().then_some(last)
+        })
+        .filter(|last| *last > episode);
+    if !cursor.at_boundary() && last_episode.is_none() {
+        return None;
+    }
+    Some(EpisodeName {
+        season: Some(season),
+        episode,
+        last_episode: last_episode.unwrap_or(episode),
+    })
+}
+
+/// `1x05`, `1x05-1x06` or `1x05-06`. Resolutions like `1920x1080` are not matched.
+fn parse_cross(name: &[char], i: usize) -> Option<EpisodeName> {
+    if i > 0 && name[i - 1].is_alphanumeric() {
+        return None;
+    }
+    let mut cursor = Cursor { name, i };
+    let season = cursor.number(1, 2)?;
+    if !cursor.eat("x") {
+        return None;
+    }
+    let episode = cursor.number(2, 3)?;
+    if !cursor.at_boundary() {
+        return None;
+    }
+    let last_episode = cursor
+        .attempt(|cursor| {
+            if !cursor.eat("-") {
+                return None;
+            }
+            cursor.attempt(|cursor| {
+                let next_season = cursor.number(1, 2)?;
+                (next_season == season && cursor.eat("x")).then_some(())
+            });
+            let last = cursor.number(2, 3)?;
+            cursor.at_boundary().then_some(last)
+        })
+        .filter(|last| *last > episode);
+    Some(EpisodeName {
+        season: Some(season),
+        episode,
+        last_episode: last_episode.unwrap_or(episode),
+    })
+}
+
+/// `Episode 12`, `Episode.12`, `Ep12` or `Ep 12-13`, without a season.
+fn parse_episode_word(name: &[char], i: usize) -> Option<EpisodeName> {
+    if !starts_word(name, i) {
+        return None;
+    }
+    let mut cursor = Cursor { name, i };
+    if !cursor.eat("episode") && !cursor.eat("ep") {
+        return None;
+    }
+    cursor.eat_separator();
+    let episode = cursor.number(1, 4)?;
+    if !cursor.at_boundary() {
+        return None;
+    }
+    let last_episode = cursor
+        .attempt(|cursor| {
+            if !cursor.eat("-") {
+                return None;
+            }
+            let last = cursor.number(1, 4)?;
+            cursor.at_boundary().then_some(last)
+        })
+        .filter(|last| *last > episode);
+    Some(EpisodeName {
+        season: None,
+        episode,
+        last_episode: last_episode.unwrap_or(episode),
+    })
+}
+
+/// Words of release names which mark the end of the series name, in lowercase.
+const RELEASE_TAGS: [&str; 30] = [
+    "web", "web-dl", "webdl", "webrip", "web-rip", "bluray", "blu-ray", "bdrip", "brrip", "hdtv",
+    "dvdrip", "dvd", "x264", "x265", "h264", "h265", "hevc", "xvid", "aac", "ac3", "dts", "10bit",
+    "hdr", "remux", "proper", "repack", "amzn", "nf", "complete", "season",
+];
+
+/// A readable name for a series from the name of its directory, e.g. `The Expanse` from
+/// `The.Expanse.S01.1080p.WEB-DL.x264-GROUP` or `Doctor Who` from `[Group] Doctor Who (2005)`.
+///
+/// Dots and underscores become spaces, a leading `[Group]` is dropped and the name ends before
+/// the first year, resolution, season or release tag. Returns the directory name if nothing
+/// is left.
+pub(crate) fn series_name(directory_name: &str) -> String {
+    let mut rest = directory_name.trim();
+    if let Some((_, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+        rest = after;
+    }
+    let mut words = vec![];
+    for word in rest.split([' ', '.', '_']).filter(|word| !word.is_empty()) {
+        let bare = word.trim_matches(|c| matches!(c, '(' | ')' | '[' | ']' | '{' | '}'));
+        if !words.is_empty() && is_release_tag(bare) {
+            break;
+        }
+        words.push(word);
+    }
+    let name = words.join(" ");
+    let name = name.trim_end_matches([' ', '-']);
+    if name.is_empty() {
+        directory_name.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// A year, resolution, season number or release tag, optionally followed by `-GROUP`.
+fn is_release_tag(word: &str) -> bool {
+    let word = word.to_lowercase();
+    let tag = word.split_once('-').map_or(word.as_str(), |(tag, _)| tag);
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let is_year =
+        |s: &str| s.len() == 4 && s.parse::<u32>().is_ok_and(|y| (1900..=2099).contains(&y));
+    let is_resolution = |s: &str| {
+        s == "4k"
+            || s.strip_suffix('p')
+                .is_some_and(|n| digits(n) && n.len() >= 3)
+            || s.split_once('x')
+                .is_some_and(|(w, h)| digits(w) && digits(h) && w.len() >= 3)
+    };
+    let is_season = |s: &str| {
+        s.strip_prefix('s').is_some_and(|rest| {
+            let number: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            !number.is_empty()
+                && (rest.len() == number.len() || rest[number.len()..].starts_with('e'))
+        })
+    };
+    let is_tag =
+        |s: &str| RELEASE_TAGS.contains(&s) || is_year(s) || is_resolution(s) || is_season(s);
+    is_tag(tag) || is_tag(&word)
+}
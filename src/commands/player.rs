@@ -0,0 +1,201 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::errors::{Result, UpNextError};
+use crate::output::note;
+use crate::schema::{Series, Settings, WatchedThreshold};
+
+mod custom;
+pub(crate) mod mpv;
+mod vlc;
+
+/// Where and how to start playing an episode, based on what was saved for the series.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct PlaybackStart {
+    /// Number of the episode, starting at 1.
+    pub(crate) episode: i64,
+    /// Seek to this position in seconds.
+    pub(crate) position: Option<u64>,
+    /// Audio track id chosen in an earlier episode.
+    pub(crate) audio_track: Option<String>,
+    /// Subtitle track id chosen in an earlier episode.
+    pub(crate) subtitle_track: Option<String>,
+}
+
+impl From<&Series> for PlaybackStart {
+    fn from(series: &Series) -> Self {
+        PlaybackStart {
+            episode: series.next_episode,
+            position: series.position_seconds,
+            audio_track: series.audio_track.clone(),
+            subtitle_track: series.subtitle_track.clone(),
+        }
+    }
+}
+
+/// What the player reported about the playback after it exited.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Playback {
+    /// Last known position in seconds.
+    pub(crate) position: Option<u64>,
+    /// Length of the episode in seconds.
+    pub(crate) duration: Option<u64>,
+    /// Whether the player reported that playback reached the end of the file.
+    pub(crate) reached_end: bool,
+    /// Audio track id selected when the player exited.
+    pub(crate) audio_track: Option<String>,
+    /// Subtitle track id selected when the player exited, `no` if subtitles were off.
+    pub(crate) subtitle_track: Option<String>,
+}
+
+impl Playback {
+    /// Whether enough of the episode was played to count it as watched. Players that do not
+    /// report their position are assumed to have played the whole episode.
+    pub(super) fn is_watched(&self, threshold: &WatchedThreshold) -> bool {
+        if self.reached_end {
+            return true;
+        }
+        match (self.position, self.duration) {
+            (Some(position), Some(duration)) => match threshold {
+                WatchedThreshold::Percent(percent) => {
+                    position * 100 >= duration * u64::from(*percent)
+                }
+                WatchedThreshold::Remaining(seconds) => position + seconds >= duration,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// A video player that can be used to play episodes.
+///
+/// Implement this trait to add support for a new player. The command logic only
+/// talks to players through this trait.
+pub(crate) trait Player: std::fmt::Debug {
+    /// Name of the player, used in messages.
+    fn name(&self) -> &str;
+
+    /// Play the file as described by `start` and block until the player exits.
+    fn play(&self, file_path: &Path, start: &PlaybackStart) -> Result<Playback>;
+
+    fn play_episode(&self, file_path: &Path, start: &PlaybackStart) -> Result<Playback> {
+        match start.position {
+            Some(start) => note!(
+                "Resuming episode \"{}\" from {} at {}.\n",
+                file_path.file_name().unwrap().to_string_lossy(),
+                crate::utils::format_seconds(start),
+                chrono::Local::now().format("%H:%M")
+            ),
+            None => note!(
+                "Starting episode \"{}\" at {}.\n",
+                file_path.file_name().unwrap().to_string_lossy(),
+                chrono::Local::now().format("%H:%M")
+            ),
+        }
+        self.play(file_path, start)
+    }
+
+    fn countdown_to_next_episode(&self, episode_delay_seconds: u64) {
+        note!("Playing next episode in {episode_delay_seconds} seconds...");
+        for i in (0..episode_delay_seconds).rev() {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            note!("{i}");
+        }
+    }
+}
+
+/// Select the player from the `player` setting, falling back to VLC.
+pub(super) fn from_config(settings: &Settings) -> Result<Box<dyn Player>> {
+    from_spec(
+        settings.player.as_deref().unwrap_or("vlc"),
+        settings.fullscreen(),
+        settings.player_args(),
+    )
+}
+
+/// Parse a player specification.
+///
+/// `vlc`, `mpv` and `mplayer` select the built-in backends, optionally followed by
+/// `:<executable>` to override the binary (e.g. `mpv:/opt/mpv/bin/mpv`). Anything else is
+/// treated as a command template, see `custom::Custom`.
+pub(crate) fn from_spec(
+    spec: &str,
+    fullscreen: bool,
+    args: Vec<String>,
+) -> Result<Box<dyn Player>> {
+    let spec = spec.trim();
+    let (name, executable) = match spec.split_once(':') {
+        Some((name, executable)) => (name, Some(executable.to_string())),
+        None => (spec, None),
+    };
+    match name {
+        "vlc" => Ok(Box::new(vlc::Vlc {
+            executable,
+            fullscreen,
+            args,
+        })),
+        "mpv" => Ok(Box::new(mpv::Mpv {
+            executable,
+            fullscreen,
+            args,
+        })),
+        "mplayer" => Ok(Box::new(Mplayer {
+            executable,
+            fullscreen,
+            args,
+        })),
+        _ => Ok(Box::new(custom::Custom::parse(spec, args)?)),
+    }
+}
+
+#[derive(Debug)]
+struct Mplayer {
+    executable: Option<String>,
+    fullscreen: bool,
+    args: Vec<String>,
+}
+
+impl Player for Mplayer {
+    fn name(&self) -> &str {
+        "mplayer"
+    }
+
+    fn play(&self, file_path: &Path, start: &PlaybackStart) -> Result<Playback> {
+        let mut command = Command::new(self.executable.as_deref().unwrap_or("mplayer"));
+        if let Some(start) = start.position {
+            command.arg("-ss").arg(start.to_string());
+        }
+        if self.fullscreen {
+            command.arg("-fs");
+        }
+        command.args(&self.args).arg(file_path);
+        run(self.name(), &mut command)?;
+        Ok(Playback::default())
+    }
+}
+
+/// Run the player command to completion and map the outcome to an `UpNextError`.
+fn run(name: &str, command: &mut Command) -> Result<()> {
+    match command.output() {
+        Ok(output) => check_exit_status(name, output.status),
+        Err(e) => Err(spawn_error(name, command, e)),
+    }
+}
+
+fn check_exit_status(name: &str, status: std::process::ExitStatus) -> Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(UpNextError::PlayerError(format!(
+            "{name} exited with status: {status}"
+        )))
+    }
+}
+
+fn spawn_error(name: &str, command: &Command, e: std::io::Error) -> UpNextError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        UpNextError::PlayerCommandNotFoundError(command.get_program().to_string_lossy().to_string())
+    } else {
+        UpNextError::PlayerError(format!("{name}: {e}"))
+    }
+}
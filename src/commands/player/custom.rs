@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::{run, Playback, PlaybackStart, Player};
+use crate::commands::discovery::SUBTITLE_EXTENSIONS;
+use crate::errors::{Result, UpNextError};
+
+/// A value substituted into the command template when playing an episode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Placeholder {
+    /// Path of the episode file.
+    File,
+    /// Position to resume from in seconds.
+    Start,
+    /// Subtitle file next to the episode with the same name.
+    Subtitle,
+    /// Episode number.
+    Episode,
+}
+
+impl Placeholder {
+    const ALL: [(&'static str, Placeholder); 4] = [
+        ("file", Placeholder::File),
+        ("start", Placeholder::Start),
+        ("subtitle", Placeholder::Subtitle),
+        ("episode", Placeholder::Episode),
+    ];
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, placeholder)| *placeholder)
+    }
+
+    fn value(self, file_path: &Path, start: &PlaybackStart) -> Option<String> {
+        match self {
+            Placeholder::File => Some(file_path.to_string_lossy().to_string()),
+            Placeholder::Start => start.position.map(|position| position.to_string()),
+            Placeholder::Subtitle => {
+                find_subtitle(file_path).map(|path| path.to_string_lossy().to_string())
+            }
+            Placeholder::Episode => Some(start.episode.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+/// A player command template such as `celluloid --fullscreen {file}`.
+///
+/// An argument containing a placeholder without a value (no `{start}` when playing from the
+/// beginning, no `{subtitle}` file) is left out. If there is no `{file}` placeholder the file
+/// is appended. Extra player arguments from the settings are added at the end.
+#[derive(Debug)]
+pub(super) struct Custom {
+    program: String,
+    args: Vec<Vec<Part>>,
+}
+
+impl Custom {
+    /// Parse and validate the template, including that the program can be found.
+    pub(super) fn parse(template: &str, extra_args: Vec<String>) -> Result<Self> {
+        let mut words = split_words(template)?.into_iter();
+        let program = words.next().ok_or_else(|| {
+            UpNextError::ConfigError("Player command must not be empty".to_string())
+        })?;
+        if program.contains('{') {
+            return Err(UpNextError::ConfigError(format!(
+                "The player program in \"{template}\" can not be a placeholder"
+            )));
+        }
+        let mut args = words
+            .map(|word| parse_word(&word, template))
+            .collect::<Result<Vec<_>>>()?;
+        let has_file = args
+            .iter()
+            .flatten()
+            .any(|part| *part == Part::Placeholder(Placeholder::File));
+        if !has_file {
+            args.push(vec![Part::Placeholder(Placeholder::File)]);
+        }
+        args.extend(extra_args.into_iter().map(|arg| vec![Part::Text(arg)]));
+        if !program_exists(&program) {
+            return Err(UpNextError::PlayerCommandNotFoundError(program));
+        }
+        Ok(Custom { program, args })
+    }
+
+    fn render_args(&self, file_path: &Path, start: &PlaybackStart) -> Vec<String> {
+        self.args
+            .iter()
+            .filter_map(|parts| {
+                parts
+                    .iter()
+                    .map(|part| match part {
+                        Part::Text(text) => Some(text.clone()),
+                        Part::Placeholder(placeholder) => placeholder.value(file_path, start),
+                    })
+                    .collect::<Option<String>>()
+            })
+            .collect()
+    }
+}
+
+impl Player for Custom {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn play(&self, file_path: &Path, start: &PlaybackStart) -> Result<Playback> {
+        let mut command = Command::new(&self.program);
+        command.args(self.render_args(file_path, start));
+        run(self.name(), &mut command)?;
+        Ok(Playback::default())
+    }
+}
+
+/// Split the template into words on whitespace, respecting single and double quotes.
+fn split_words(template: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in template.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(UpNextError::ConfigError(format!(
+            "Unterminated quote in player command \"{template}\""
+        )));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+fn parse_word(word: &str, template: &str) -> Result<Vec<Part>> {
+    let mut parts = vec![];
+    let mut rest = word;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            parts.push(Part::Text(rest[..open].to_string()));
+        }
+        let close = rest[open..].find('}').ok_or_else(|| {
+            UpNextError::ConfigError(format!(
+                "Unclosed placeholder in player command \"{template}\""
+            ))
+        })? + open;
+        let name = &rest[open + 1..close];
+        let placeholder = Placeholder::parse(name).ok_or_else(|| {
+            let known: Vec<String> = Placeholder::ALL
+                .iter()
+                .map(|(name, _)| format!("{{{name}}}"))
+                .collect();
+            UpNextError::ConfigError(format!(
+                "Unknown placeholder {{{name}}} in player command \"{template}\", expected one of {}",
+                known.join(", ")
+            ))
+        })?;
+        parts.push(Part::Placeholder(placeholder));
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+fn program_exists(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+fn find_subtitle(file_path: &Path) -> Option<PathBuf> {
+    SUBTITLE_EXTENSIONS
+        .iter()
+        .map(|extension| file_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
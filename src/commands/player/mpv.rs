@@ -0,0 +1,153 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use super::{check_exit_status, spawn_error, Playback, PlaybackStart, Player};
+use crate::errors::Result;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Properties observed through the IPC socket. The index is used as the observer id.
+const OBSERVED_PROPERTIES: [&str; 4] = ["time-pos", "duration", "aid", "sid"];
+
+/// mpv, followed through its JSON IPC socket (`--input-ipc-server`) while it plays.
+#[derive(Debug)]
+pub(super) struct Mpv {
+    pub(super) executable: Option<String>,
+    pub(super) fullscreen: bool,
+    pub(super) args: Vec<String>,
+}
+
+impl Player for Mpv {
+    fn name(&self) -> &str {
+        "mpv"
+    }
+
+    fn play(&self, file_path: &Path, start: &PlaybackStart) -> Result<Playback> {
+        let socket_path = ipc_socket_path();
+        let mut command = Command::new(self.executable.as_deref().unwrap_or("mpv"));
+        command.arg(format!("--input-ipc-server={}", socket_path.display()));
+        if let Some(position) = start.position {
+            command.arg(format!("--start={position}"));
+        }
+        if let Some(audio_track) = &start.audio_track {
+            command.arg(format!("--aid={audio_track}"));
+        }
+        if let Some(subtitle_track) = &start.subtitle_track {
+            command.arg(format!("--sid={subtitle_track}"));
+        }
+        if self.fullscreen {
+            command.arg("--fullscreen");
+        }
+        command
+            .args(&self.args)
+            .arg(file_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        let mut child = command
+            .spawn()
+            .map_err(|e| spawn_error(self.name(), &command, e))?;
+
+        let playback = match connect(&socket_path, &mut child)? {
+            Some(stream) => watch_ipc(stream)?,
+            None => Playback::default(),
+        };
+        let status = child.wait()?;
+        let _ = std::fs::remove_file(&socket_path);
+        check_exit_status(self.name(), status)?;
+        Ok(playback)
+    }
+}
+
+fn ipc_socket_path() -> PathBuf {
+    std::env::temp_dir().join(format!("upnext-mpv-{}.sock", std::process::id()))
+}
+
+/// Wait for mpv to create the IPC socket. Returns `None` if mpv exits or never creates it.
+fn connect(socket_path: &Path, child: &mut Child) -> Result<Option<UnixStream>> {
+    let started = Instant::now();
+    while started.elapsed() < CONNECT_TIMEOUT {
+        if child.try_wait()?.is_some() {
+            return Ok(None);
+        }
+        if let Ok(stream) = UnixStream::connect(socket_path) {
+            return Ok(Some(stream));
+        }
+        std::thread::sleep(CONNECT_RETRY_INTERVAL);
+    }
+    Ok(None)
+}
+
+/// Observe the playback properties and collect them until mpv closes the connection.
+pub(crate) fn watch_ipc(stream: UnixStream) -> Result<Playback> {
+    let mut writer = stream.try_clone()?;
+    for (id, property) in OBSERVED_PROPERTIES.iter().enumerate() {
+        writeln!(
+            writer,
+            "{}",
+            json!({ "command": ["observe_property", id, property] })
+        )?;
+    }
+
+    let mut playback = Playback::default();
+    let mut ended = false;
+    for line in BufReader::new(stream).lines() {
+        // mpv may reset the connection when it quits
+        let Ok(line) = line else { break };
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        match message["event"].as_str() {
+            // Properties are reset while the file is unloaded, only the values before matter.
+            Some("property-change") if !ended => apply_property_change(&mut playback, &message),
+            Some("end-file") => {
+                ended = true;
+                playback.reached_end = message["reason"] == "eof";
+            }
+            _ => {}
+        }
+    }
+    Ok(playback)
+}
+
+fn apply_property_change(playback: &mut Playback, message: &Value) {
+    let data = &message["data"];
+    match message["name"].as_str() {
+        Some("time-pos") => {
+            if let Some(position) = data.as_f64() {
+                playback.position = Some(position as u64);
+            }
+        }
+        Some("duration") => {
+            if let Some(duration) = data.as_f64() {
+                playback.duration = Some(duration as u64);
+            }
+        }
+        Some("aid") => {
+            if let Some(id) = track_id(data) {
+                playback.audio_track = Some(id);
+            }
+        }
+        Some("sid") => {
+            if let Some(id) = track_id(data) {
+                playback.subtitle_track = Some(id);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// mpv reports a selected track as its id and a disabled track as `false`.
+fn track_id(data: &Value) -> Option<String> {
+    match data {
+        Value::Number(id) => Some(id.to_string()),
+        Value::Bool(false) => Some("no".to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use super::{check_exit_status, spawn_error, Playback, PlaybackStart, Player};
+use crate::errors::{Result, UpNextError};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// VLC, queried through its remote control interface on stdin/stdout while it plays.
+#[derive(Debug)]
+pub(super) struct Vlc {
+    pub(super) executable: Option<String>,
+    pub(super) fullscreen: bool,
+    pub(super) args: Vec<String>,
+}
+
+impl Player for Vlc {
+    fn name(&self) -> &str {
+        "VLC"
+    }
+
+    fn play(&self, file_path: &Path, start: &PlaybackStart) -> Result<Playback> {
+        if let Some(executable) = &self.executable {
+            return self.play_in_vlc_helper(&mut Command::new(executable), file_path, start);
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let mut flatpak_command = Command::new("flatpak");
+            let flatpak_vlc_command = flatpak_command.arg("run").arg("org.videolan.VLC");
+
+            let res = self.play_in_vlc_helper(flatpak_vlc_command, file_path, start);
+
+            match res {
+                Err(UpNextError::PlayerCommandNotFoundError(_)) => {
+                    eprintln!("VLC flatpak command not found. Trying to run VLC directly.\n");
+                    let mut vlc_command = Command::new("vlc");
+                    self.play_in_vlc_helper(&mut vlc_command, file_path, start)
+                }
+                other => other,
+            }
+        }
+        #[cfg(target_os = "macos")]
+        {
+            let mut vlc_command = Command::new("/Applications/VLC.app/Contents/MacOS/VLC");
+            self.play_in_vlc_helper(&mut vlc_command, file_path, start)
+        }
+    }
+}
+
+impl Vlc {
+    fn play_in_vlc_helper(
+        &self,
+        vlc_command: &mut Command,
+        file_path: &Path,
+        start: &PlaybackStart,
+    ) -> Result<Playback> {
+        vlc_command
+            .arg(file_path)
+            .arg("--play-and-exit")
+            .arg("--extraintf=rc")
+            .arg("--rc-fake-tty");
+        if self.fullscreen {
+            vlc_command.arg("--fullscreen");
+        }
+        if let Some(position) = start.position {
+            vlc_command.arg(format!("--start-time={position}"));
+        }
+        vlc_command.args(&self.args);
+        let mut child = vlc_command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| spawn_error("VLC", vlc_command, e))?;
+
+        let playback = poll_playback(&mut child)?;
+        check_exit_status("VLC", child.wait()?)?;
+        Ok(playback)
+    }
+}
+
+/// Ask VLC for the position and length until it exits.
+fn poll_playback(child: &mut Child) -> Result<Playback> {
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let responses = spawn_response_reader(child);
+    let mut playback = Playback::default();
+
+    while child.try_wait()?.is_none() {
+        // VLC reports 0 when nothing is playing, e.g. right after the end of the episode.
+        if let Some(position) = query(&mut stdin, &responses, "get_time").filter(|&p| p > 0) {
+            playback.position = Some(position);
+        }
+        if let Some(duration) = query(&mut stdin, &responses, "get_length").filter(|&d| d > 0) {
+            playback.duration = Some(duration);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    Ok(playback)
+}
+
+/// Forward every numeric line VLC prints to the returned channel.
+fn spawn_response_reader(child: &mut Child) -> Receiver<u64> {
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            if let Some(number) = parse_response(&line) {
+                if sender.send(number).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/// Parse a numeric answer, ignoring the prompt. Other output such as status changes is ignored.
+fn parse_response(line: &str) -> Option<u64> {
+    line.trim().trim_start_matches('>').trim().parse().ok()
+}
+
+fn query(stdin: &mut ChildStdin, responses: &Receiver<u64>, command: &str) -> Option<u64> {
+    // Drop late answers to earlier queries
+    while responses.try_recv().is_ok() {}
+    writeln!(stdin, "{command}").ok()?;
+    stdin.flush().ok()?;
+    responses.recv_timeout(RESPONSE_TIMEOUT).ok()
+}
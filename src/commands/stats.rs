@@ -0,0 +1,249 @@
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
+use serde::Serialize;
+
+use crate::commands::utils::find_series_files;
+use crate::history::HistoryEntry;
+use crate::schema::{SeriesList, SeriesStatus};
+use crate::utils::format_seconds;
+
+const WEEK_SECONDS: f64 = 7.0 * 24.0 * 3600.0;
+
+/// Viewing statistics from the history, as printed by `stats`.
+#[derive(Serialize)]
+pub(super) struct Stats {
+    /// Time the player was open, summed over all sessions.
+    total_seconds: u64,
+    sessions: usize,
+    episodes_watched: usize,
+    /// Watched episodes per week between the first and the last played episode, at least a
+    /// week.
+    episodes_per_week: f64,
+    /// Most consecutive days with at least one watched episode.
+    longest_streak_days: usize,
+    most_watched: Option<SeriesStats>,
+    average_session_seconds: u64,
+    unfinished: Vec<Projection>,
+}
+
+/// The series with the most watched episodes.
+#[derive(Serialize)]
+struct SeriesStats {
+    name: String,
+    path: String,
+    episodes_watched: usize,
+    total_seconds: u64,
+}
+
+/// When an unfinished series is done at the pace it was watched so far.
+#[derive(Serialize)]
+struct Projection {
+    name: String,
+    path: String,
+    remaining_episodes: usize,
+    /// Remaining episodes times their average length reported by the player, `None` if the
+    /// player never reported one.
+    remaining_seconds: Option<u64>,
+    /// `None` if no episode of the series was watched yet, same for `projected_completion`.
+    episodes_per_week: Option<f64>,
+    projected_completion: Option<NaiveDate>,
+}
+
+pub(super) fn compute(
+    entries: &[HistoryEntry],
+    series_list: &SeriesList,
+    now: DateTime<Local>,
+) -> Stats {
+    let watched: Vec<&HistoryEntry> = entries.iter().filter(|entry| entry.completed).collect();
+    let total_seconds = entries.iter().map(HistoryEntry::session_seconds).sum();
+
+    let mut by_series: HashMap<&str, SeriesStats> = HashMap::new();
+    for entry in entries {
+        let stats = by_series
+            .entry(&entry.series)
+            .or_insert_with(|| SeriesStats {
+                name: String::new(),
+                path: entry.series.clone(),
+                episodes_watched: 0,
+                total_seconds: 0,
+            });
+        // The latest name wins, the series may have been renamed since
+        stats.name.clone_from(&entry.name);
+        stats.episodes_watched += usize::from(entry.completed);
+        stats.total_seconds += entry.session_seconds();
+    }
+    let most_watched = by_series
+        .into_values()
+        .filter(|stats| stats.episodes_watched > 0)
+        .max_by(|a, b| {
+            (a.episodes_watched, a.total_seconds, &b.name).cmp(&(
+                b.episodes_watched,
+                b.total_seconds,
+                &a.name,
+            ))
+        });
+
+    let unfinished = series_list
+        .series
+        .iter()
+        .filter(|series| {
+            !matches!(
+                series.status(),
+                SeriesStatus::Completed | SeriesStatus::Dropped
+            )
+        })
+        .filter_map(|series| {
+            let total = find_series_files(series_list, &series.path)?.len();
+            let watched_episodes = usize::try_from(series.next_episode - 1).unwrap_or(0);
+            let remaining_episodes = total.checked_sub(watched_episodes).filter(|n| *n > 0)?;
+            let series_entries: Vec<&HistoryEntry> = entries
+                .iter()
+                .filter(|entry| entry.series == series.path)
+                .collect();
+            let durations: Vec<u64> = series_entries
+                .iter()
+                .filter_map(|entry| entry.duration_seconds)
+                .collect();
+            let remaining_seconds = (!durations.is_empty()).then(|| {
+                durations.iter().sum::<u64>() / durations.len() as u64 * remaining_episodes as u64
+            });
+            let series_watched: Vec<&HistoryEntry> = series_entries
+                .into_iter()
+                .filter(|entry| entry.completed)
+                .collect();
+            let episodes_per_week = (!series_watched.is_empty()).then(|| per_week(&series_watched));
+            let projected_completion = episodes_per_week.and_then(|per_week| {
+                let days = (remaining_episodes as f64 / per_week * 7.0).ceil() as i64;
+                TimeDelta::try_days(days).map(|days| (now + days).date_naive())
+            });
+            Some(Projection {
+                name: series.title().to_string(),
+                path: series.path.clone(),
+                remaining_episodes,
+                remaining_seconds,
+                episodes_per_week: episodes_per_week.map(round),
+                projected_completion,
+            })
+        })
+        .collect();
+
+    Stats {
+        total_seconds,
+        sessions: entries.len(),
+        episodes_watched: watched.len(),
+        episodes_per_week: if watched.is_empty() {
+            0.0
+        } else {
+            round(per_week(&watched))
+        },
+        longest_streak_days: longest_streak(&watched),
+        most_watched,
+        average_session_seconds: match entries.len() {
+            0 => 0,
+            sessions => total_seconds / sessions as u64,
+        },
+        unfinished,
+    }
+}
+
+/// The number of entries per week between the first and the last of them, counting at least a
+/// week so that a few episodes on one evening do not make a huge pace.
+fn per_week(watched: &[&HistoryEntry]) -> f64 {
+    let first = watched.iter().map(|entry| entry.started).min();
+    let last = watched.iter().map(|entry| entry.ended).max();
+    let seconds = match (first, last) {
+        (Some(first), Some(last)) => (last - first).num_seconds() as f64,
+        _ => 0.0,
+    };
+    watched.len() as f64 / (seconds / WEEK_SECONDS).max(1.0)
+}
+
+fn longest_streak(watched: &[&HistoryEntry]) -> usize {
+    let days: BTreeSet<NaiveDate> = watched
+        .iter()
+        .map(|entry| entry.started.date_naive())
+        .collect();
+    let mut longest = 0;
+    let mut streak = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for day in days {
+        streak = match previous {
+            Some(previous) if previous.succ_opt() == Some(day) => streak + 1,
+            _ => 1,
+        };
+        longest = longest.max(streak);
+        previous = Some(day);
+    }
+    longest
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+pub(super) fn print(stats: &Stats) {
+    println!(
+        "Time watched:      {} in {} sessions",
+        format_seconds(stats.total_seconds),
+        stats.sessions
+    );
+    println!(
+        "Episodes watched:  {}, {:.1} per week",
+        stats.episodes_watched, stats.episodes_per_week
+    );
+    println!(
+        "Longest streak:    {} day{}",
+        stats.longest_streak_days,
+        if stats.longest_streak_days == 1 {
+            ""
+        } else {
+            "s"
+        }
+    );
+    if let Some(most_watched) = &stats.most_watched {
+        println!(
+            "Most watched:      {}, {} episodes in {}",
+            most_watched.name,
+            most_watched.episodes_watched,
+            format_seconds(most_watched.total_seconds)
+        );
+    }
+    println!(
+        "Average session:   {}",
+        format_seconds(stats.average_session_seconds)
+    );
+
+    if stats.unfinished.is_empty() {
+        return;
+    }
+    let name_width = stats
+        .unfinished
+        .iter()
+        .map(|projection| projection.name.chars().count())
+        .chain(["Unfinished".len()])
+        .max()
+        .unwrap_or_default();
+    println!();
+    println!(
+        "{:<name_width$}  {:>4}  {:>9}  {:>8}  Done by",
+        "Unfinished", "Left", "Time left", "Per week"
+    );
+    for projection in &stats.unfinished {
+        let dash = || "-".to_string();
+        println!(
+            "{:<name_width$}  {:>4}  {:>9}  {:>8}  {}",
+            projection.name,
+            projection.remaining_episodes,
+            projection
+                .remaining_seconds
+                .map_or_else(dash, format_seconds),
+            projection
+                .episodes_per_week
+                .map_or_else(dash, |per_week| format!("{per_week:.1}")),
+            projection
+                .projected_completion
+                .map_or_else(dash, |date| date.to_string()),
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::path::PathBuf;
+
+use crate::commands::discovery::{self, relative_name};
+use crate::commands::utils::confirm;
+use crate::errors::Result;
+use crate::output::note;
+use crate::schema::{Series, SeriesStatus, SortOrder};
+
+/// Remember the file at `index` as the last watched episode of the series.
+pub(super) fn record_watched(series: &mut Series, files: &[PathBuf], index: usize) {
+    let file_path = &files[index];
+    series.last_watched_file = Some(relative_name(series, file_path));
+    series.last_watched_size = std::fs::metadata(file_path).ok().map(|m| m.len());
+    series.episode_count = u64::try_from(files.len()).ok();
+}
+
+/// Update the last watched episode after `next_episode` was changed by hand. If the files can
+/// not be listed the last watched episode is forgotten.
+pub(super) fn record_next_episode(series: &mut Series, files: Option<&[PathBuf]>) {
+    let last_watched = files.and_then(|files| {
+        usize::try_from(series.next_episode - 2)
+            .ok()
+            .filter(|i| *i < files.len())
+            .map(|i| (files, i))
+    });
+    match last_watched {
+        Some((files, index)) => record_watched(series, files, index),
+        None => {
+            series.last_watched_file = None;
+            series.last_watched_size = None;
+            series.episode_count = None;
+        }
+    }
+}
+
+/// Merge what playing changed from `loaded` to `played` into `series`, the same series loaded
+/// again after the playback. What another upnext process changed in the meantime is kept: if
+/// the next episode was changed, e.g. with `inc`, the progress of the playback is dropped.
+pub(super) fn merge_progress(series: &mut Series, loaded: &Series, played: &Series) {
+    if series.next_episode == loaded.next_episode {
+        series.next_episode = played.next_episode;
+        series.position_seconds = played.position_seconds;
+        series
+            .last_watched_file
+            .clone_from(&played.last_watched_file);
+        series.last_watched_size = played.last_watched_size;
+        series.episode_count = played.episode_count;
+    } else if played.next_episode != loaded.next_episode {
+        note!(
+            "The next episode of \"{}\" was changed to {} while it played, keeping that.\n",
+            series.title(),
+            series.next_episode
+        );
+    }
+    if series.status == loaded.status {
+        series.status = played.status;
+    }
+    series.audio_track.clone_from(&played.audio_track);
+    series.subtitle_track.clone_from(&played.subtitle_track);
+    series.last_watched = played.last_watched;
+}
+
+/// Change the progress of `series` from `from` to `to`, the same series before and after a
+/// command. Only the fields which differ between them are changed, so that edits made since,
+/// e.g. with `rename` or `status`, are kept.
+pub(super) fn change_progress(series: &mut Series, from: &Series, to: &Series) {
+    change_field(
+        &mut series.next_episode,
+        &from.next_episode,
+        &to.next_episode,
+    );
+    change_field(
+        &mut series.position_seconds,
+        &from.position_seconds,
+        &to.position_seconds,
+    );
+    change_field(&mut series.audio_track, &from.audio_track, &to.audio_track);
+    change_field(
+        &mut series.subtitle_track,
+        &from.subtitle_track,
+        &to.subtitle_track,
+    );
+    change_field(
+        &mut series.last_watched_file,
+        &from.last_watched_file,
+        &to.last_watched_file,
+    );
+    change_field(
+        &mut series.last_watched_size,
+        &from.last_watched_size,
+        &to.last_watched_size,
+    );
+    change_field(
+        &mut series.episode_count,
+        &from.episode_count,
+        &to.episode_count,
+    );
+    change_field(
+        &mut series.last_watched,
+        &from.last_watched,
+        &to.last_watched,
+    );
+    change_field(&mut series.status, &from.status, &to.status);
+}
+
+fn change_field<T: PartialEq + Clone>(field: &mut T, from: &T, to: &T) {
+    if from != to {
+        field.clone_from(to);
+    }
+}
+
+/// Mark the series completed once all episodes are watched, and watching again if it was
+/// completed but episodes are left, e.g. after `set` or when new episodes were added. A
+/// directory without episodes does not count as completed.
+pub(super) fn update_completion(series: &mut Series, total: usize) {
+    let watched = usize::try_from(series.next_episode - 1).unwrap_or(0);
+    if total > 0 && watched >= total {
+        series.status = Some(SeriesStatus::Completed);
+    } else if series.status() == SeriesStatus::Completed {
+        series.status = None;
+    }
+}
+
+/// Re-derive `next_episode` from the last watched file, so that adding or deleting episodes
+/// does not break the progress. Returns whether the series was changed.
+///
+/// If the last watched file is gone, or `next_episode` does not follow it while the number of
+/// episodes is the same, explain what changed and propose where to continue. When
+/// `interactive` the user is asked to accept the proposal, otherwise it is only printed. `sort`
+/// is the order of `files`.
+pub(super) fn resync(
+    series: &mut Series,
+    files: &[PathBuf],
+    sort: SortOrder,
+    interactive: bool,
+) -> Result<bool> {
+    let Some(last_watched) = series.last_watched_file.clone() else {
+        return Ok(false);
+    };
+
+    let found = files
+        .iter()
+        .position(|f| relative_name(series, f) == last_watched);
+    let next_episode = match (found, find_renamed(series, files)) {
+        (Some(i), _) => {
+            let next_episode = i64::try_from(i)? + 2;
+            if next_episode == series.next_episode {
+                return Ok(false);
+            }
+            let count_changed = series
+                .episode_count
+                .is_some_and(|count| Some(count) != u64::try_from(files.len()).ok());
+            if count_changed {
+                note!(
+                    "The episodes in the directory changed, the last watched episode \"{last_watched}\" is now episode {}. \
+                     Next episode updated from {} to {next_episode}.\n",
+                    i + 1,
+                    series.next_episode
+                );
+                series.next_episode = next_episode;
+                record_next_episode(series, Some(files));
+                return Ok(true);
+            }
+            // Without added or deleted episodes the next episode was probably set by hand
+            note!(
+                "The next episode {} does not follow the last watched episode \"{last_watched}\" (episode {}).",
+                series.next_episode,
+                i + 1
+            );
+            next_episode
+        }
+        (None, Some(i)) => {
+            note!(
+                "The last watched episode \"{last_watched}\" is no longer in the directory, \
+                 but \"{}\" has the same size and was probably renamed from it.",
+                relative_name(series, &files[i])
+            );
+            i64::try_from(i)? + 2
+        }
+        (None, None) => {
+            note!(
+                "The last watched episode \"{last_watched}\" is no longer in the directory, \
+                 it was probably deleted or renamed."
+            );
+            // The episode after it is the first one sorted after its name
+            let i = discovery::position_of_missing(series, files, &last_watched, sort);
+            i64::try_from(i)? + 1
+        }
+    };
+
+    if next_episode != series.next_episode {
+        match usize::try_from(next_episode - 1).ok().and_then(|i| files.get(i)) {
+            Some(file_path) => note!(
+                "The next episode is probably \"{}\" (episode {next_episode}), the stored next episode is {}.",
+                relative_name(series, file_path),
+                series.next_episode
+            ),
+            None => note!(
+                "There are probably no episodes left (next episode {next_episode}), the stored next episode is {}.",
+                series.next_episode
+            ),
+        }
+        if !interactive {
+            note!(
+                "Run `{} set {next_episode}` to continue from there.\n",
+                crate::APP_NAME
+            );
+            return Ok(false);
+        }
+        if confirm("Continue from there? [Y/n]")? {
+            series.next_episode = next_episode;
+        }
+    }
+    record_next_episode(series, Some(files));
+    Ok(true)
+}
+
+/// The index of the only file with the same size as the last watched episode.
+fn find_renamed(series: &Series, files: &[PathBuf]) -> Option<usize> {
+    let size = series.last_watched_size?;
+    let mut same_size = files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| std::fs::metadata(f).map(|m| m.len()).ok() == Some(size));
+    match (same_size.next(), same_size.next()) {
+        (Some((i, _)), None) => Some(i),
+        _ => None,
+    }
+}
@@ -4,12 +4,14 @@ use std::fmt::Display;
 pub enum UpNextError {
     GenericError(String),
     IoError(std::io::Error),
-    VlcError(String),
-    VlcCommandNotFoundError,
+    PlayerError(String),
+    PlayerCommandNotFoundError(String),
     SchemaError(String),
+    ConfigError(String),
     MissingSeries,
+    UnknownSeries(String),
+    AmbiguousSeries(String, Vec<String>),
     SeriesAlreadyExists,
-    SeriesOver,
     WrongEpisodeNumber,
     Unimplemented,
 }
@@ -19,23 +21,28 @@ impl Display for UpNextError {
         match self {
             UpNextError::GenericError(e) => write!(f, "Error: {e}"),
             UpNextError::IoError(e) => write!(f, "IO error: {e}"),
-            UpNextError::VlcError(e) => write!(f, "VLC error: {e}"),
-            UpNextError::VlcCommandNotFoundError => write!(
+            UpNextError::PlayerError(e) => write!(f, "Player error: {e}"),
+            UpNextError::PlayerCommandNotFoundError(command) => write!(
                 f,
-                "VLC command not found. Please ensure VLC is installed and in your PATH."
+                "Player command `{command}` not found. Please ensure it is installed and in your PATH."
             ),
             UpNextError::SchemaError(e) => write!(f, "Schema error: {e}"),
+            UpNextError::ConfigError(e) => write!(f, "Configuration error: {e}"),
             UpNextError::MissingSeries => write!(
                 f,
                 "No series found for current working directory. Please run `{} init` first.",
                 crate::APP_NAME
             ),
+            UpNextError::UnknownSeries(selector) => {
+                write!(f, "No series found for `{selector}`.")
+            }
+            UpNextError::AmbiguousSeries(selector, paths) => {
+                write!(f, "`{selector}` matches several series, please be more specific:")?;
+                paths.iter().try_for_each(|path| write!(f, "\n  {path}"))
+            }
             UpNextError::SeriesAlreadyExists => {
                 write!(f, "Current directory is already initialized.")
             }
-            UpNextError::SeriesOver => {
-                write!(f, "Season is over. No more episodes left in directory.")
-            }
             UpNextError::WrongEpisodeNumber => {
                 write!(f, "\nCanceled due to episode numbering out of sync.")
             }
@@ -44,6 +51,26 @@ impl Display for UpNextError {
     }
 }
 
+impl UpNextError {
+    /// Name of the variant in the `kind` field of JSON error documents.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            UpNextError::GenericError(_) => "generic",
+            UpNextError::IoError(_) => "io",
+            UpNextError::PlayerError(_) => "player",
+            UpNextError::PlayerCommandNotFoundError(_) => "player_command_not_found",
+            UpNextError::SchemaError(_) => "schema",
+            UpNextError::ConfigError(_) => "config",
+            UpNextError::MissingSeries => "missing_series",
+            UpNextError::UnknownSeries(_) => "unknown_series",
+            UpNextError::AmbiguousSeries(_, _) => "ambiguous_series",
+            UpNextError::SeriesAlreadyExists => "series_already_exists",
+            UpNextError::WrongEpisodeNumber => "wrong_episode_number",
+            UpNextError::Unimplemented => "unimplemented",
+        }
+    }
+}
+
 impl From<std::io::Error> for UpNextError {
     fn from(e: std::io::Error) -> Self {
         UpNextError::IoError(e)
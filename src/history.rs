@@ -0,0 +1,96 @@
+use std::fs;
+use std::io::{self, Write};
+
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Result, UpNextError};
+
+/// An episode played once, a line in the history file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    /// Path of the series.
+    pub series: String,
+    /// Name of the series when the episode was played.
+    pub name: String,
+    /// The episode file relative to the series path.
+    pub file: String,
+    pub started: DateTime<Local>,
+    pub ended: DateTime<Local>,
+    /// Whether the episode counted as watched.
+    pub completed: bool,
+    /// Where playback stopped, if the player reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position_seconds: Option<u64>,
+    /// Length of the episode, if the player reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<u64>,
+}
+
+impl HistoryEntry {
+    /// How long the player was open.
+    pub fn session_seconds(&self) -> u64 {
+        u64::try_from((self.ended - self.started).num_seconds()).unwrap_or(0)
+    }
+}
+
+/// Add the entry at the end of the history file, one JSON object per line. The file is only
+/// ever appended to, so a crash can at most lose the last entry. The line is written at once,
+/// so that the lines of upnext processes appending at the same time can not interleave.
+pub fn append(entry: &HistoryEntry) -> Result<()> {
+    let line = serde_json::to_string(entry)
+        .map_err(|e| UpNextError::GenericError(format!("Could not write history: {e}")))?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(crate::utils::get_history_path()?)?;
+    file.write_all(format!("{line}\n").as_bytes())?;
+    Ok(())
+}
+
+/// All entries in the order they were played. A missing file is an empty history.
+pub fn read() -> Result<Vec<HistoryEntry>> {
+    let path = crate::utils::get_history_path()?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| UpNextError::SchemaError(format!("{path} line {}: {e}", i + 1)))
+        })
+        .collect()
+}
+
+/// The start of a `--since` period: a date like `2026-03-01` or a time ago like `12h`, `7d`
+/// or `2w`.
+pub fn parse_since(since: &str, now: DateTime<Local>) -> Result<DateTime<Local>> {
+    let invalid = || {
+        UpNextError::ConfigError(format!(
+            "Invalid time `{since}`, expected a date like `2026-03-01` or a time ago like `12h`, `7d` or `2w`"
+        ))
+    };
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|time| time.and_local_timezone(Local).earliest())
+            .ok_or_else(invalid);
+    }
+    let unit_start = since
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let amount: i64 = since[..unit_start].parse().map_err(|_| invalid())?;
+    let ago = match &since[unit_start..] {
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    Ok(now - ago)
+}
@@ -0,0 +1,132 @@
+use std::fs;
+use std::io;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Result, UpNextError};
+use crate::schema::Series;
+
+/// How many changes can be undone.
+const MAX_CHANGES: usize = 50;
+
+/// The changes which can be undone and the undone changes which can be redone, most recent
+/// last.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Journal {
+    #[serde(default)]
+    pub undo: Vec<Change>,
+    #[serde(default)]
+    pub redo: Vec<Change>,
+}
+
+/// The series changed by a command.
+#[derive(Serialize, Deserialize)]
+pub struct Change {
+    /// The command and the series, e.g. ``"`set 42` on \"Kids Show\""``.
+    pub description: String,
+    pub time: DateTime<Local>,
+    pub series: Vec<SeriesChange>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SeriesChange {
+    pub path: String,
+    /// Position in the list of series, to put a removed series back where it was.
+    pub index: usize,
+    /// `None` if the series was added.
+    pub before: Option<Series>,
+    /// `None` if the series was removed.
+    pub after: Option<Series>,
+    /// The toml table of a removed series with its comments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table: Option<String>,
+}
+
+impl Change {
+    /// The differences between the series `before` and `after` a command, `table` gives the
+    /// toml text of a removed series.
+    pub fn new(
+        description: String,
+        before: &[Series],
+        after: &[Series],
+        table: impl Fn(&str) -> Result<Option<String>>,
+    ) -> Result<Self> {
+        let mut series = vec![];
+        for (index, old) in before.iter().enumerate() {
+            match after.iter().find(|new| new.path == old.path) {
+                Some(new) if same(old, new)? => {}
+                Some(new) => series.push(SeriesChange {
+                    path: old.path.clone(),
+                    index,
+                    before: Some(old.clone()),
+                    after: Some(new.clone()),
+                    table: None,
+                }),
+                None => series.push(SeriesChange {
+                    path: old.path.clone(),
+                    index,
+                    before: Some(old.clone()),
+                    after: None,
+                    table: table(&old.path)?,
+                }),
+            }
+        }
+        for (index, new) in after.iter().enumerate() {
+            if !before.iter().any(|old| old.path == new.path) {
+                series.push(SeriesChange {
+                    path: new.path.clone(),
+                    index,
+                    before: None,
+                    after: Some(new.clone()),
+                    table: None,
+                });
+            }
+        }
+        Ok(Change {
+            description,
+            time: Local::now(),
+            series,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+}
+
+fn same(a: &Series, b: &Series) -> Result<bool> {
+    let to_json = |series| {
+        serde_json::to_value(series)
+            .map_err(|e| UpNextError::GenericError(format!("Could not compare series: {e}")))
+    };
+    Ok(to_json(a)? == to_json(b)?)
+}
+
+/// Add a change to undo. New changes can not be redone after what was undone before.
+pub fn record(change: Change) -> Result<()> {
+    let mut journal = read()?;
+    journal.undo.push(change);
+    let excess = journal.undo.len().saturating_sub(MAX_CHANGES);
+    journal.undo.drain(..excess);
+    journal.redo.clear();
+    write(&journal)
+}
+
+/// The journal, empty if the file is missing.
+pub fn read() -> Result<Journal> {
+    let path = crate::utils::get_journal_path()?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Journal::default()),
+        Err(e) => return Err(e.into()),
+    };
+    serde_json::from_str(&content).map_err(|e| UpNextError::SchemaError(format!("{path}: {e}")))
+}
+
+pub fn write(journal: &Journal) -> Result<()> {
+    let content = serde_json::to_string(journal)
+        .map_err(|e| UpNextError::GenericError(format!("Could not write the journal: {e}")))?;
+    let path = crate::utils::get_journal_path()?;
+    crate::persistence::write_atomically(std::path::Path::new(&path), content.as_bytes())
+}
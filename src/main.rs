@@ -1,12 +1,21 @@
-use clap::{CommandFactory, Parser, Subcommand};
+#![allow(clippy::unit_arg)]
+
+use clap::{Args, CommandFactory, Parser, Subcommand};
 
 use crate::commands::{
-    edit_in_default_editor, find_series, increment, init, play, play_next_episode,
-    print_all_series_info, print_current_series_info, print_toml_path, remove, set_next_episode,
+    edit_in_default_editor, edit_order, find_series, increment, init, list_files, play,
+    play_next_episode, print_all_series_info, print_current_series_info, print_history,
+    print_stats, print_toml_path, queue, redo, regenerate_order, remove, rename, set_next_episode,
+    set_status, undo, ListFilter, ListSort,
 };
+use crate::output::OutputFormat;
+use crate::schema::{SeriesStatus, Settings};
 
 mod commands;
 mod errors;
+mod history;
+mod journal;
+mod output;
 mod persistence;
 mod schema;
 #[cfg(test)]
@@ -15,68 +24,209 @@ mod utils;
 
 pub static APP_NAME: &str = "upnext";
 pub static TOML_PATH_ENV_VAR_NAME: &str = "UPNEXT_TOML_PATH"; // keep docs below in sync if changed
+pub static PLAYER_ENV_VAR_NAME: &str = "UPNEXT_PLAYER"; // keep docs below in sync if changed
+pub static WATCHED_THRESHOLD_ENV_VAR_NAME: &str = "UPNEXT_WATCHED_THRESHOLD"; // keep docs below in sync if changed
 
 /// A simple CLI app to keep track of your progress in watching TV shows, series.
 #[derive(Parser)]
 #[command(
     long_about = "This simple app helps you keep track of the progress in the TV shows you are watching. \n\
-                  Data is saved in the file `~/.upnext.toml`. For each show, it saves the path and the \n\
-                  episode number. If you change the path or delete, add, rename (reorder) episodes the \n\
-                  tracking will be broken. You can fix it by editing the file. Use the app to play next \n\
-                  episodes automatically in VLC.\n\
+                  Data is saved in the file `~/.upnext.toml`. For each show, it saves the path, the \n\
+                  episode number and the file name of the last watched episode. Adding or deleting episodes \n\
+                  is detected from the file name. If you change the path the tracking will be broken. You \n\
+                  can fix it by editing the file. Use the app to play next episodes automatically in VLC.\n\
                   You can override the location where the data is saved by setting the environment variable \n\
-                  `UPNEXT_TOML_PATH` to the desired path."
+                  `UPNEXT_TOML_PATH` to the desired path.\n\
+                  Settings can be given in a `[settings]` table in the same file: `player`, `player_args`, \n\
+                  `delay_seconds`, `extensions`, `fullscreen`, `watched_threshold`, `recursive`, `sort` and \n\
+                  `ignore`. A series can override `player`, `delay_seconds`, `fullscreen`, `recursive` and \n\
+                  `sort` and add `player_args`, `extra_extensions` and `ignore` in its own table. Files \n\
+                  matching the gitignore-style `ignore` patterns or a `.upnextignore` file are not episodes. \n\
+                  A recursive series (`init --recursive`) also plays the episodes in season subdirectories, \n\
+                  in order of their season number. `sort` is `natural` (default, by season and episode \n\
+                  numbers), `lexical`, `mtime` or `manual`, which plays the episodes in the order of the \n\
+                  `order` list of the series or the `.upnext-order` file in its directory, see the `order` \n\
+                  command.\n\
+                  Without a command, `upnext` shows the queue of unfinished series, most recently watched \n\
+                  first, and plays the next episode of the chosen one.\n\
+                  A series is `completed` once its last episode is watched. `status paused` leaves it out of \n\
+                  the queue, `status dropped` also hides it from `list` and `find` unless `--all` is given.\n\
+                  `info`, `next`, `play`, `inc`, `set` and `remove` act on the series in the current \n\
+                  directory, or on the one chosen with `--series` by name, path or `#<n>` from anywhere.\n\
+                  `init` names the series after its directory without release tags like the year or \n\
+                  resolution, `rename` changes the name and sets aliases.\n\
+                  Every played episode is added to the history next to the toml file, see `history`, and \n\
+                  `stats` sums it up.\n\
+                  `undo` reverts the last change of `init`, `inc`, `set`, `next`, `play` or `remove` and \n\
+                  `redo` applies it again, the changes are kept next to the toml file.\n\
+                  With `--output json`, `info`, `list`, `find`, `next`, `inc`, `set`, `history`, `stats` and \n\
+                  `which` print a JSON document with a `schema_version` and errors as \n\
+                  `{\"error\": {\"kind\", \"message\"}}`.\n\
+                  Command line options override environment variables, which override the settings.\n\
+                  The player can be chosen with the environment variable `UPNEXT_PLAYER`: `vlc` (default), \n\
+                  `mpv` or `mplayer`, optionally followed by `:<executable>`, or any other command which \n\
+                  is then called with the episode file as its last argument.\n\
+                  An episode only counts as watched if enough of it was played, set with the environment \n\
+                  variable `UPNEXT_WATCHED_THRESHOLD` as a percentage (`90%`, the default) or as time \n\
+                  before the end (`3m`, `180s`). This requires a player that reports its position (VLC or mpv)."
 )]
 struct Cli {
+    /// Print text or, for `info`, `list`, `find`, `next`, `inc`, `set`, `history`, `stats` and
+    /// `which`, a JSON document.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize the current directory as a series.
     #[command(name = "init")]
-    Init,
+    Init {
+        /// Also play the episodes in subdirectories, e.g. `Season 01`, `Season 02`, as one series.
+        #[arg(long, short = 'r')]
+        recursive: bool,
+    },
     /// Start playing series (autoplay next episode).
     #[command(name = "play")]
     Play {
-        /// Delay between episodes in seconds. If not specified, defaults to 5 seconds.
-        #[arg(long, short = 'd', default_value_t = 5)]
-        delay_seconds: u64,
+        /// Delay between episodes in seconds. If not specified, defaults to the `delay_seconds` setting or 5 seconds.
+        #[arg(long, short = 'd')]
+        delay_seconds: Option<u64>,
+        #[command(flatten)]
+        series_args: SeriesArgs,
+        #[command(flatten)]
+        player_args: PlayerArgs,
     },
     /// Play next episode.
     #[command(name = "next")]
-    Next,
+    Next {
+        #[command(flatten)]
+        series_args: SeriesArgs,
+        #[command(flatten)]
+        player_args: PlayerArgs,
+    },
+    /// List the unfinished series, most recently watched first, and pick one to play.
+    #[command(name = "queue")]
+    Queue {
+        /// Also show the paused and dropped series.
+        #[arg(long, short = 'a')]
+        all: bool,
+        #[command(flatten)]
+        player_args: PlayerArgs,
+    },
     /// Print the series information in the current directory.
     #[command(name = "info")]
-    Info,
+    Info {
+        #[command(flatten)]
+        series_args: SeriesArgs,
+    },
     /// Increment the episode number by the given amount.
     #[command(name = "inc")]
     IncrementEpisode {
         /// Increment by this amount. If the new episode number is equal to the total number of episodes, the series is considered complete.
         #[arg(default_value_t = 1)]
         n: i64,
+        #[command(flatten)]
+        series_args: SeriesArgs,
     },
     /// Set the next episode number explicitly.
     #[command(name = "set")]
     SetNextEpisode {
         /// The episode number to set. Starts at 1. If equal to the total number of episodes, the series is considered complete.
         n: u32,
+        #[command(flatten)]
+        series_args: SeriesArgs,
+    },
+    /// Set the name the series is shown with and chosen by with `--series`.
+    #[command(name = "rename")]
+    Rename {
+        /// The new name. An empty name shows the directory name again.
+        name: String,
+        /// Another name to choose the series by, can be repeated. Replaces the existing aliases.
+        #[arg(long = "alias", short = 'a')]
+        aliases: Vec<String>,
+        #[command(flatten)]
+        series_args: SeriesArgs,
+    },
+    /// Set the status of the series: `watching`, `paused` (left out of the queue), `completed`
+    /// or `dropped` (hidden unless asked for with `--all`).
+    #[command(name = "status")]
+    Status {
+        status: SeriesStatus,
+        #[command(flatten)]
+        series_args: SeriesArgs,
     },
     /// Remove data about the series in current directory.
     #[command(name = "remove")]
-    Remove,
-    /// Print all series information.
+    Remove {
+        #[command(flatten)]
+        series_args: SeriesArgs,
+    },
+    /// Undo the last change of `init`, `inc`, `set`, `next`, `play` or `remove`.
+    #[command(name = "undo")]
+    Undo,
+    /// Redo the last undone change.
+    #[command(name = "redo")]
+    Redo,
+    /// Print a table of all series with their progress.
     #[command(name = "list")]
-    List,
+    List {
+        /// Order of the series, by default as in the toml file.
+        #[arg(long, value_enum)]
+        sort: Option<ListSort>,
+        /// Only show the series with this status.
+        #[arg(long, value_enum)]
+        filter: Option<ListFilter>,
+        /// Also show the dropped series.
+        #[arg(long, short = 'a')]
+        all: bool,
+        /// Print the toml file as it is instead.
+        #[arg(long, conflicts_with_all = ["sort", "filter", "all"])]
+        raw: bool,
+    },
+    /// Print the played episodes with when and how long they were played.
+    #[command(name = "history")]
+    History {
+        /// Only the episodes played since a date like `2026-03-01` or a time ago like `12h`,
+        /// `7d` or `2w`.
+        #[arg(long)]
+        since: Option<String>,
+        /// Only the episodes of this series: its name, an alias, its path, a part of them or
+        /// `#<n>`. Defaults to all series.
+        #[arg(long, short = 's', value_name = "NAME|PATH|#N")]
+        series: Option<String>,
+    },
+    /// Print the time watched, the pace and when the unfinished series will be done.
+    #[command(name = "stats")]
+    Stats {
+        /// Only count the episodes played since a date like `2026-03-01` or a time ago like
+        /// `12h`, `7d` or `2w`.
+        #[arg(long)]
+        since: Option<String>,
+    },
     /// Open the toml file in the default editor.
     #[command(name = "edit")]
     Edit,
-    /// Find and print all series where the path contains the search string.
+    /// List the episode files of the series and the ignored files.
+    #[command(name = "files")]
+    Files,
+    /// Manage the manual episode order of the series (`sort = "manual"`).
+    #[command(name = "order")]
+    Order {
+        #[command(subcommand)]
+        command: OrderCommands,
+    },
+    /// Find and print all series whose path, name or one of the aliases contains the search
+    /// string, ignoring case.
     #[command(name = "find")]
     Find {
         /// The search term.
         search_term: String,
+        /// Also show the dropped series.
+        #[arg(long, short = 'a')]
+        all: bool,
     },
     /// Print the path to the toml file. (For debugging purposes.)
     #[command(name = "which")]
@@ -89,20 +239,148 @@ enum Commands {
     },
 }
 
+impl Commands {
+    fn has_json_output(&self) -> bool {
+        matches!(
+            self,
+            Commands::Info { .. }
+                | Commands::List { .. }
+                | Commands::Find { .. }
+                | Commands::Next { .. }
+                | Commands::IncrementEpisode { .. }
+                | Commands::SetNextEpisode { .. }
+                | Commands::History { .. }
+                | Commands::Stats { .. }
+                | Commands::Which
+        )
+    }
+}
+
+#[derive(Subcommand)]
+enum OrderCommands {
+    /// Open the episode order in `$EDITOR`, creating it from the current order if needed.
+    #[command(name = "edit")]
+    Edit,
+    /// Replace the episode order with the current natural (or configured) order.
+    #[command(name = "regenerate")]
+    Regenerate,
+}
+
+/// Option choosing the series to act on instead of the one in the current directory.
+#[derive(Args)]
+struct SeriesArgs {
+    /// The series to use: its name, an alias, its path, a part of them or `#<n>` for the n-th
+    /// series in the toml file. Defaults to the series in the current directory.
+    #[arg(long, short = 's', value_name = "NAME|PATH|#N")]
+    series: Option<String>,
+}
+
+/// Options overriding the `[settings]` in the toml file.
+#[derive(Args)]
+struct PlayerArgs {
+    /// The player to use: `vlc`, `mpv`, `mplayer` or a command template like `celluloid {file}`.
+    #[arg(long)]
+    player: Option<String>,
+    /// Start the player in fullscreen.
+    #[arg(long, overrides_with = "no_fullscreen")]
+    fullscreen: bool,
+    /// Do not start the player in fullscreen.
+    #[arg(long, overrides_with = "fullscreen")]
+    no_fullscreen: bool,
+    /// How much of an episode has to be played to count as watched, e.g. `90%` or `3m` before the end.
+    #[arg(long)]
+    watched_threshold: Option<String>,
+}
+
+impl PlayerArgs {
+    fn to_settings(&self) -> errors::Result<Settings> {
+        Ok(Settings {
+            player: self.player.clone(),
+            fullscreen: match (self.fullscreen, self.no_fullscreen) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            watched_threshold: self
+                .watched_threshold
+                .as_deref()
+                .map(str::parse)
+                .transpose()?,
+            ..Settings::default()
+        })
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
+    output::set_format(cli.output);
 
-    let res = match &cli.command {
-        Commands::Init => init(),
-        Commands::Play { delay_seconds } => play(*delay_seconds),
-        Commands::Next => play_next_episode(),
-        Commands::Info => print_current_series_info(),
-        Commands::IncrementEpisode { n } => increment(*n),
-        Commands::SetNextEpisode { n } => set_next_episode(*n),
-        Commands::Remove => remove(),
-        Commands::List => print_all_series_info(),
+    let Some(command) = &cli.command else {
+        return report(unless_json().and_then(|()| queue(false, Settings::default())));
+    };
+    if !command.has_json_output() {
+        if let Err(e) = unless_json() {
+            return report(Err(e));
+        }
+    }
+    let res = match command {
+        Commands::Init { recursive } => init(*recursive),
+        Commands::Play {
+            delay_seconds,
+            series_args,
+            player_args,
+        } => player_args.to_settings().and_then(|settings| {
+            play(
+                series_args.series.as_deref(),
+                Settings {
+                    delay_seconds: *delay_seconds,
+                    ..settings
+                },
+            )
+        }),
+        Commands::Next {
+            series_args,
+            player_args,
+        } => player_args
+            .to_settings()
+            .and_then(|settings| play_next_episode(series_args.series.as_deref(), settings)),
+        Commands::Queue { all, player_args } => player_args
+            .to_settings()
+            .and_then(|settings| queue(*all, settings)),
+        Commands::Info { series_args } => print_current_series_info(series_args.series.as_deref()),
+        Commands::IncrementEpisode { n, series_args } => {
+            increment(series_args.series.as_deref(), *n)
+        }
+        Commands::SetNextEpisode { n, series_args } => {
+            set_next_episode(series_args.series.as_deref(), *n)
+        }
+        Commands::Rename {
+            name,
+            aliases,
+            series_args,
+        } => rename(series_args.series.as_deref(), name, aliases),
+        Commands::Remove { series_args } => remove(series_args.series.as_deref()),
+        Commands::List {
+            sort,
+            filter,
+            all,
+            raw,
+        } => print_all_series_info(*raw, *sort, *filter, *all),
+        Commands::Status {
+            status,
+            series_args,
+        } => set_status(series_args.series.as_deref(), *status),
+        Commands::History { since, series } => print_history(series.as_deref(), since.as_deref()),
+        Commands::Stats { since } => print_stats(since.as_deref()),
+        Commands::Undo => undo(),
+        Commands::Redo => redo(),
         Commands::Edit => edit_in_default_editor(),
-        Commands::Find { search_term } => find_series(search_term),
+        Commands::Files => list_files(),
+        Commands::Order { command } => match command {
+            OrderCommands::Edit => edit_order(),
+            OrderCommands::Regenerate => regenerate_order(),
+        },
+        Commands::Find { search_term, all } => find_series(search_term, *all),
         Commands::Which => print_toml_path(),
         Commands::Completions { shell } => Ok(clap_complete::generate(
             *shell,
@@ -111,8 +389,25 @@ fn main() {
             &mut std::io::stdout(),
         )),
     };
+    report(res);
+}
+
+/// Refuse `--output json` for commands without JSON output, instead of mixing text into it.
+fn unless_json() -> errors::Result<()> {
+    if output::is_json() {
+        return Err(errors::UpNextError::GenericError(
+            "This command has no JSON output, it is supported by `info`, `list`, `find`, `next`, \
+             `inc`, `set`, `history`, `stats` and `which`"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
 
-    if let Err(e) = res {
-        eprintln!("{e}");
+fn report(res: errors::Result<()>) {
+    match res {
+        Err(e) if output::is_json() => output::print_json_error(&e),
+        Err(e) => eprintln!("{e}"),
+        Ok(()) => {}
     }
 }
@@ -0,0 +1,85 @@
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+use crate::errors::{Result, UpNextError};
+
+/// Version of the JSON documents printed with `--output json`, in their `schema_version` field.
+/// It is increased when a field is removed or changes its meaning, new fields may be added
+/// without a new version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text.
+    #[default]
+    Text,
+    /// One JSON document on stdout, messages go to stderr.
+    Json,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Set the output format for the rest of the process, it can only be set once.
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn is_json() -> bool {
+    FORMAT.get() == Some(&OutputFormat::Json)
+}
+
+/// Print a message about what is happening, like `println!`. With `--output json` it goes to
+/// stderr, so that stdout only has the JSON document.
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if crate::output::is_json() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+pub(crate) use note;
+
+#[derive(Serialize)]
+struct Document<'a, T> {
+    schema_version: u32,
+    #[serde(flatten)]
+    content: &'a T,
+}
+
+/// Print `content` as a JSON object with the `schema_version` added.
+pub fn print_json<T: Serialize>(content: &T) -> Result<()> {
+    let document = Document {
+        schema_version: SCHEMA_VERSION,
+        content,
+    };
+    let json = serde_json::to_string_pretty(&document)
+        .map_err(|e| UpNextError::GenericError(format!("Could not write JSON: {e}")))?;
+    Ok(println!("{json}"))
+}
+
+#[derive(Serialize)]
+struct ErrorDocument<'a> {
+    error: ErrorContent<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorContent<'a> {
+    kind: &'a str,
+    message: String,
+}
+
+/// Print the error as `{"error": {"kind": ..., "message": ...}}`.
+pub fn print_json_error(error: &UpNextError) {
+    let document = ErrorDocument {
+        error: ErrorContent {
+            kind: error.kind(),
+            message: error.to_string().trim().to_string(),
+        },
+    };
+    if let Err(e) = print_json(&document) {
+        eprintln!("{e}");
+    }
+}
@@ -1,4 +1,9 @@
-use std::{fmt::Display, fs, io, io::Write, path::Path};
+use std::{
+    fmt::Display,
+    fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use toml_edit::{value, ArrayOfTables, DocumentMut, Table};
 
@@ -6,8 +11,19 @@ use crate::errors::{Result, UpNextError};
 use crate::schema::{Series, SeriesList};
 
 pub fn read_toml_file<P: AsRef<Path>>(path: P) -> Result<SeriesList> {
-    let content = fs::read_to_string(path)?;
+    let content = fs::read_to_string(&path)?;
     if content.is_empty() {
+        // An empty file is more likely a failed write than a wish to forget all series
+        let backup = backup_path(path.as_ref(), 1);
+        if fs::metadata(&backup).is_ok_and(|metadata| metadata.len() > 0) {
+            return Err(UpNextError::SchemaError(format!(
+                "{} is empty, but its backup is not. Restore the backup with `cp {} {}` or \
+                 delete the backup to start over.",
+                path.as_ref().display(),
+                backup.display(),
+                path.as_ref().display()
+            )));
+        }
         return Ok(SeriesList::new());
     }
     let series_list: SeriesList = toml::from_str(&content)
@@ -17,17 +33,175 @@ pub fn read_toml_file<P: AsRef<Path>>(path: P) -> Result<SeriesList> {
 
 // Read the existing TOML file for the comments and formatting. Then apply
 // changes from `series_list` while preserving comments. Alternatively create
-// a new TOML DocumentMut if file doesn't exist.
+// a new TOML DocumentMut if file doesn't exist. The `[settings]` table is only
+// edited by hand, so it is left as it is.
 pub fn write_toml_file<P: AsRef<Path>>(path: P, series_list: &SeriesList) -> Result<()> {
     let mut doc = create_or_load_toml_doc(&path)?;
 
     update_or_create_list_of_series(&mut doc, series_list)?;
 
-    let mut file = fs::File::create(path)?;
-    file.write_all(doc.to_string().as_bytes())?;
+    write_doc(path, &doc)
+}
+
+fn write_doc<P: AsRef<Path>>(path: P, doc: &DocumentMut) -> Result<()> {
+    let path = resolve_symlink(path.as_ref())?;
+    rotate_backups(&path)?;
+    write_atomically(&path, doc.to_string().as_bytes())
+}
+
+/// The lock file of the toml file at `path`, e.g. `~/.upnext.toml.lock`. The toml file itself
+/// can not be locked as it is replaced on every write.
+pub fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Take the advisory lock of the toml file at `path`, `waiting` is called first if another
+/// process holds it. The lock is released when the returned file is closed.
+pub fn lock<P: AsRef<Path>>(path: P, waiting: impl FnOnce()) -> Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path(path.as_ref()))?;
+    match file.try_lock() {
+        Ok(()) => return Ok(file),
+        Err(fs::TryLockError::WouldBlock) => waiting(),
+        Err(fs::TryLockError::Error(e)) => return Err(e.into()),
+    }
+    file.lock()?;
+    Ok(file)
+}
+
+/// How many backups of the toml file are kept, `.upnext.toml.bak.1` is the most recent.
+pub const BACKUPS: usize = 3;
+
+/// The path of the `n`th backup of the file at `path`.
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".bak.{n}"));
+    path.with_file_name(name)
+}
+
+/// Keep a copy of the file before it is replaced, shifting the older copies. An empty file is
+/// not worth a backup, it would only push out the good ones.
+fn rotate_backups(path: &Path) -> Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() > 0 => {}
+        Ok(_) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+    for n in (1..BACKUPS).rev() {
+        let backup = backup_path(path, n);
+        if backup.exists() {
+            fs::rename(&backup, backup_path(path, n + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
     Ok(())
 }
 
+/// The file a symlink points to, so that replacing the file keeps the link.
+fn resolve_symlink(path: &Path) -> Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Ok(target) => Ok(target),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match fs::read_link(path) {
+            // A link to a file which does not exist yet
+            Ok(target) => Ok(path.parent().unwrap_or(Path::new("")).join(target)),
+            Err(_) => Ok(path.to_path_buf()),
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace the file at `path` with `content`, so that a crash, a full disk or Ctrl-C leave
+/// either the old or the new file and never a truncated one: the content is written to a
+/// temporary file in the same directory, flushed to disk and renamed over the file. The
+/// permissions of the file are kept.
+pub fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(format!(".tmp.{}", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let write = || -> Result<()> {
+        let mut file = fs::File::create(&temp_path)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        // Also make the rename itself durable
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir)?.sync_all()?;
+        Ok(())
+    };
+    let result = write();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// The `[[series]]` table of the series at `series_path` as toml text with its comments, so
+/// that it can be put back with `restore_series_table` after the series was removed.
+pub fn series_table_text<P: AsRef<Path>>(path: P, series_path: &str) -> Result<Option<String>> {
+    let doc = create_or_load_toml_doc(&path)?;
+    let Some(array_of_series) = doc.get("series").and_then(|item| item.as_array_of_tables()) else {
+        return Ok(None);
+    };
+    let Some(table) = array_of_series
+        .iter()
+        .find(|table| table.get("path").and_then(|path| path.as_str()) == Some(series_path))
+    else {
+        return Ok(None);
+    };
+    let mut series_doc = DocumentMut::default();
+    let mut array = ArrayOfTables::new();
+    array.push(table.clone());
+    series_doc["series"] = toml_edit::Item::ArrayOfTables(array);
+    Ok(Some(series_doc.to_string()))
+}
+
+/// Put a series table from `series_table_text` back at `index` in the list of series, with
+/// its comments. Its values are then updated by `write_toml_file`.
+pub fn restore_series_table<P: AsRef<Path>>(path: P, index: usize, text: &str) -> Result<()> {
+    let series_doc = text.parse::<DocumentMut>()?;
+    let Some(mut table) = series_doc
+        .get("series")
+        .and_then(|item| item.as_array_of_tables())
+        .and_then(|array| array.get(0))
+        .cloned()
+    else {
+        return Err(UpNextError::SchemaError(
+            "Saved series table has no [[series]]".to_string(),
+        ));
+    };
+    let mut doc = create_or_load_toml_doc(&path)?;
+    let array_of_series = get_or_create_array_of_series(&mut doc)?;
+    let mut tables: Vec<Table> = array_of_series.iter().cloned().collect();
+    let index = index.min(tables.len());
+    // Tables are written in the order of their position in the file they were read from, so
+    // the restored table takes the position of its neighbour to stay next to it
+    let neighbour = index.checked_sub(1).or((!tables.is_empty()).then_some(0));
+    match neighbour.and_then(|i| tables[i].position()) {
+        Some(position) => table.set_position(position),
+        None => table.set_position(isize::MAX),
+    }
+    tables.insert(index, table);
+    array_of_series.clear();
+    tables
+        .into_iter()
+        .for_each(|table| array_of_series.push(table));
+    write_doc(path, &doc)
+}
+
 fn update_or_create_list_of_series(doc: &mut DocumentMut, series_list: &SeriesList) -> Result<()> {
     let array_of_series: &mut ArrayOfTables = get_or_create_array_of_series(doc)?;
 
@@ -35,7 +209,7 @@ fn update_or_create_list_of_series(doc: &mut DocumentMut, series_list: &SeriesLi
 
     for series in &series_list.series {
         let series_table: &mut Table = get_or_create_series_table(array_of_series, series)?;
-        update_or_create_next_episode(series_table, series.next_episode)?;
+        update_series_table(series_table, series)?;
     }
 
     Ok(())
@@ -51,8 +225,7 @@ impl Display for Series {
             get_or_create_array_of_series(&mut doc).map_err(|_| core::fmt::Error)?;
         let series_table: &mut Table =
             get_or_create_series_table(array_of_series, series).map_err(|_| core::fmt::Error)?;
-        update_or_create_next_episode(series_table, series.next_episode)
-            .map_err(|_| core::fmt::Error)?;
+        update_series_table(series_table, series).map_err(|_| core::fmt::Error)?;
         let toml_data = series_table;
         write!(f, "[[series]]\n{toml_data}")
     }
@@ -92,7 +265,7 @@ fn get_or_create_array_of_series(doc: &mut DocumentMut) -> Result<&mut ArrayOfTa
     }
 }
 
-fn remove_deleted_series(array_of_series: &mut ArrayOfTables, series_list: &SeriesList) -> () {
+fn remove_deleted_series(array_of_series: &mut ArrayOfTables, series_list: &SeriesList) {
     array_of_series.retain(|table| {
         table
             .get("path")
@@ -158,25 +331,108 @@ fn get_or_create_series_table<'a>(
     }
 }
 
-fn update_or_create_next_episode(
-    series_table: &mut toml_edit::Table,
-    next_episode: i64,
+// Only the names, the progress and the per-series settings changed by commands
+// (`recursive`, `sort` and `order`) are written. The other per-series settings are
+// edited by hand and kept as they are together with their comments.
+fn update_series_table(series_table: &mut Table, series: &Series) -> Result<()> {
+    update_or_remove_value(series_table, "name", series.name.as_deref())?;
+    update_or_remove_list(series_table, "aliases", series.aliases.as_deref());
+    update_or_create_value(series_table, "next_episode", series.next_episode)?;
+    let position_seconds = series.position_seconds.map(i64::try_from).transpose()?;
+    update_or_remove_value(series_table, "position_seconds", position_seconds)?;
+    update_or_remove_value(series_table, "audio_track", series.audio_track.as_deref())?;
+    update_or_remove_value(
+        series_table,
+        "subtitle_track",
+        series.subtitle_track.as_deref(),
+    )?;
+    update_or_remove_value(
+        series_table,
+        "last_watched_file",
+        series.last_watched_file.as_deref(),
+    )?;
+    let last_watched_size = series.last_watched_size.map(i64::try_from).transpose()?;
+    update_or_remove_value(series_table, "last_watched_size", last_watched_size)?;
+    let episode_count = series.episode_count.map(i64::try_from).transpose()?;
+    update_or_remove_value(series_table, "episode_count", episode_count)?;
+    let last_watched = series
+        .last_watched
+        .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, false));
+    update_or_remove_value(series_table, "last_watched", last_watched)?;
+    let status = series.status.map(|status| status.to_string());
+    update_or_remove_value(series_table, "status", status)?;
+    update_or_remove_value(series_table, "recursive", series.settings.recursive)?;
+    let sort = series.settings.sort.map(|sort| sort.to_string());
+    update_or_remove_value(series_table, "sort", sort)?;
+    update_or_remove_list(series_table, "order", series.settings.order.as_deref());
+    Ok(())
+}
+
+// A list is only rewritten if it changed, one value per line, so that a list
+// formatted by hand keeps its comments.
+fn update_or_remove_list(series_table: &mut Table, key: &str, new_list: Option<&[String]>) {
+    let Some(new_list) = new_list else {
+        series_table.remove(key);
+        return;
+    };
+    let current_list: Option<Vec<&str>> = series_table
+        .get(key)
+        .and_then(|item| item.as_array())
+        .and_then(|array| array.iter().map(|value| value.as_str()).collect());
+    if current_list.is_some_and(|current_list| current_list == new_list) {
+        return;
+    }
+    let mut array: toml_edit::Array = new_list.iter().collect();
+    for value in array.iter_mut() {
+        value.decor_mut().set_prefix("\n    ");
+    }
+    array.set_trailing_comma(true);
+    array.set_trailing("\n");
+    let decor = series_table
+        .get(key)
+        .and_then(|item| item.as_value())
+        .map(|value| value.decor().clone());
+    series_table[key] = value(array);
+    if let Some(decor) = decor {
+        let new_value = series_table[key]
+            .as_value_mut()
+            .expect("just created it as a value");
+        *new_value.decor_mut() = decor;
+    }
+}
+
+fn update_or_remove_value<V: Into<toml_edit::Value>>(
+    series_table: &mut Table,
+    key: &str,
+    new_value: Option<V>,
 ) -> Result<()> {
-    if let Some(next_episode_item) = series_table.get_mut("next_episode") {
+    match new_value {
+        Some(new_value) => update_or_create_value(series_table, key, new_value),
+        None => {
+            series_table.remove(key);
+            Ok(())
+        }
+    }
+}
+
+fn update_or_create_value<V: Into<toml_edit::Value>>(
+    series_table: &mut Table,
+    key: &str,
+    new_value: V,
+) -> Result<()> {
+    if let Some(item) = series_table.get_mut(key) {
         // Get decoration
-        let decor = next_episode_item
+        let decor = item
             .as_value()
-            .ok_or_else(|| UpNextError::SchemaError("next_episode is not a value".to_string()))?
+            .ok_or_else(|| UpNextError::SchemaError(format!("{key} is not a value")))?
             .decor()
             .clone();
         // Update value
-        *next_episode_item = value(next_episode);
+        *item = value(new_value);
         // Reapply decoration
-        let new_value = next_episode_item
-            .as_value_mut()
-            .expect("just created it as a value");
+        let new_value = item.as_value_mut().expect("just created it as a value");
         Ok(*new_value.decor_mut() = decor)
     } else {
-        Ok(series_table["next_episode"] = value(next_episode))
+        Ok(series_table[key] = value(new_value))
     }
 }
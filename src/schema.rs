@@ -1,3 +1,4 @@
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
 use crate::errors::{Result, UpNextError};
@@ -5,17 +6,374 @@ use crate::errors::{Result, UpNextError};
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Series {
     pub path: String,
+    /// Display name, derived from the directory name at `init`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Other names the series can be chosen by with `--series`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<String>>,
     pub next_episode: i64,
+    /// Position in seconds within `next_episode` where playback was stopped early.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position_seconds: Option<u64>,
+    /// Audio track id to select, remembered from the last episode played with mpv.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_track: Option<String>,
+    /// Subtitle track id to select, remembered from the last episode played with mpv.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subtitle_track: Option<String>,
+    /// File name of the last watched episode relative to `path`, used to keep `next_episode` in
+    /// sync when episodes are added or deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_watched_file: Option<String>,
+    /// Size in bytes of the last watched episode, used to recognize it after a rename.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_watched_size: Option<u64>,
+    /// Number of episodes in the directory when `last_watched_file` was recorded, to tell added
+    /// or deleted episodes apart from a `next_episode` edited by hand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub episode_count: Option<u64>,
+    /// When an episode of the series was last played, used to order the `queue`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_watched: Option<DateTime<Local>>,
+    /// Set with `upnext status` or when the last episode was watched, watching if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<SeriesStatus>,
+    #[serde(flatten)]
+    pub settings: SeriesSettings,
+}
+
+impl Series {
+    /// Name of the series in messages: its `name`, or else the name of its directory.
+    pub fn title(&self) -> &str {
+        self.name
+            .as_deref()
+            .unwrap_or_else(|| self.directory_name())
+    }
+
+    pub fn directory_name(&self) -> &str {
+        std::path::Path::new(&self.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.path)
+    }
+
+    pub fn status(&self) -> SeriesStatus {
+        self.status.unwrap_or_default()
+    }
+
+    /// Whether the series is hidden from `list`, `find` and the `queue` unless asked for.
+    pub fn is_hidden(&self) -> bool {
+        self.status() == SeriesStatus::Dropped
+    }
+
+    /// Whether `name` is the title, directory name or an alias of the series, ignoring case.
+    pub fn is_called(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        [self.title(), self.directory_name()]
+            .into_iter()
+            .chain(self.aliases.iter().flatten().map(String::as_str))
+            .any(|candidate| candidate.to_lowercase() == name)
+    }
+}
+
+/// Optional per-series keys, these take precedence over the `[settings]` table.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SeriesSettings {
+    /// Player for this series, same format as the `player` setting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player: Option<String>,
+    /// Arguments passed to the player in addition to the `player_args` setting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_args: Option<Vec<String>>,
+    /// Delay between episodes when using `play`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay_seconds: Option<u64>,
+    /// Video file extensions in addition to the `extensions` setting, e.g. `["ts"]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_extensions: Option<Vec<String>>,
+    /// Ignore patterns in addition to the `ignore` setting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore: Option<Vec<String>>,
+    /// Whether the player is started in fullscreen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fullscreen: Option<bool>,
+    /// Whether episodes are also searched in subdirectories such as `Season 01`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recursive: Option<bool>,
+    /// How the episode files are ordered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<SortOrder>,
+    /// Episode files relative to `path` in the order to play them with `sort = "manual"`. If
+    /// not set, the `.upnext-order` file in the series directory is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Vec<String>>,
+}
+
+const DEFAULT_DELAY_SECONDS: u64 = 5;
+const DEFAULT_EXTENSIONS: [&str; 10] = [
+    "mkv", "mp4", "avi", "flv", "mov", "wmv", "webm", "mpg", "mpeg", "m4v",
+];
+
+/// Global settings from the `[settings]` table. Unset values fall back to built-in defaults,
+/// see the accessors.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Settings {
+    /// `vlc`, `mpv`, `mplayer` or a command template like `celluloid --fullscreen {file}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player: Option<String>,
+    /// Extra arguments passed to the player.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_args: Option<Vec<String>>,
+    /// Delay between episodes when using `play`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay_seconds: Option<u64>,
+    /// File extensions of video files, without the dot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Vec<String>>,
+    /// Whether the player is started in fullscreen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fullscreen: Option<bool>,
+    /// How much of an episode has to be played for it to count as watched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watched_threshold: Option<WatchedThreshold>,
+    /// Whether episodes are also searched in subdirectories such as `Season 01`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recursive: Option<bool>,
+    /// How the episode files are ordered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<SortOrder>,
+    /// Gitignore-style patterns of files which are not episodes, e.g. `["sample.*"]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore: Option<Vec<String>>,
+}
+
+impl Settings {
+    /// Values set in `overrides` take precedence over the values in `self`.
+    pub fn overridden_by(self, overrides: Settings) -> Settings {
+        Settings {
+            player: overrides.player.or(self.player),
+            player_args: overrides.player_args.or(self.player_args),
+            delay_seconds: overrides.delay_seconds.or(self.delay_seconds),
+            extensions: overrides.extensions.or(self.extensions),
+            fullscreen: overrides.fullscreen.or(self.fullscreen),
+            watched_threshold: overrides.watched_threshold.or(self.watched_threshold),
+            recursive: overrides.recursive.or(self.recursive),
+            sort: overrides.sort.or(self.sort),
+            ignore: overrides.ignore.or(self.ignore),
+        }
+    }
+
+    /// Apply the settings of a series. Its values take precedence, its player arguments, extra
+    /// extensions and ignore patterns are added to the global ones.
+    pub fn for_series(self, series: &SeriesSettings) -> Settings {
+        let player_args = match (&self.player_args, &series.player_args) {
+            (Some(args), Some(extra_args)) => Some([args.clone(), extra_args.clone()].concat()),
+            (args, extra_args) => extra_args.clone().or(args.clone()),
+        };
+        let ignore = match (&self.ignore, &series.ignore) {
+            (Some(patterns), Some(extra_patterns)) => {
+                Some([patterns.clone(), extra_patterns.clone()].concat())
+            }
+            (patterns, extra_patterns) => extra_patterns.clone().or(patterns.clone()),
+        };
+        let extensions = match &series.extra_extensions {
+            Some(extra_extensions) => Some([self.extensions(), extra_extensions.clone()].concat()),
+            None => self.extensions,
+        };
+        Settings {
+            player: series.player.clone().or(self.player),
+            player_args,
+            delay_seconds: series.delay_seconds.or(self.delay_seconds),
+            extensions,
+            fullscreen: series.fullscreen.or(self.fullscreen),
+            watched_threshold: self.watched_threshold,
+            recursive: series.recursive.or(self.recursive),
+            sort: series.sort.or(self.sort),
+            ignore,
+        }
+    }
+
+    pub fn player_args(&self) -> Vec<String> {
+        self.player_args.clone().unwrap_or_default()
+    }
+
+    pub fn delay_seconds(&self) -> u64 {
+        self.delay_seconds.unwrap_or(DEFAULT_DELAY_SECONDS)
+    }
+
+    pub fn extensions(&self) -> Vec<String> {
+        match &self.extensions {
+            Some(extensions) => extensions.clone(),
+            None => DEFAULT_EXTENSIONS.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    pub fn fullscreen(&self) -> bool {
+        self.fullscreen.unwrap_or(true)
+    }
+
+    pub fn watched_threshold(&self) -> WatchedThreshold {
+        self.watched_threshold.unwrap_or_default()
+    }
+
+    pub fn recursive(&self) -> bool {
+        self.recursive.unwrap_or(false)
+    }
+
+    pub fn sort(&self) -> SortOrder {
+        self.sort.unwrap_or_default()
+    }
+
+    pub fn ignore(&self) -> Vec<String> {
+        self.ignore.clone().unwrap_or_default()
+    }
+}
+
+/// How the episode files in a directory are ordered.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// By the season and episode numbers in the file names, then by name with numbers compared
+    /// by their value, so `Episode 2` comes before `Episode 10`.
+    #[default]
+    Natural,
+    /// By file name, character by character.
+    Lexical,
+    /// By modification time, oldest first.
+    Mtime,
+    /// In the order given by hand in the `order` list of the series or its `.upnext-order` file.
+    Manual,
+}
+
+impl std::fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SortOrder::Natural => "natural",
+            SortOrder::Lexical => "lexical",
+            SortOrder::Mtime => "mtime",
+            SortOrder::Manual => "manual",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Where the user is with a series.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SeriesStatus {
+    #[default]
+    Watching,
+    /// On hold, left out of the `queue`.
+    Paused,
+    /// The last episode was watched.
+    Completed,
+    /// Not going to be watched further, hidden unless asked for.
+    Dropped,
+}
+
+impl std::fmt::Display for SeriesStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SeriesStatus::Watching => "watching",
+            SeriesStatus::Paused => "paused",
+            SeriesStatus::Completed => "completed",
+            SeriesStatus::Dropped => "dropped",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// How much of an episode has to be played for it to count as watched.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum WatchedThreshold {
+    /// At least this percentage of the duration, e.g. `90%`.
+    Percent(u8),
+    /// Stopped within this many seconds of the end, e.g. `3m` or `180s`.
+    Remaining(u64),
+}
+
+impl Default for WatchedThreshold {
+    fn default() -> Self {
+        WatchedThreshold::Percent(90)
+    }
+}
+
+impl std::str::FromStr for WatchedThreshold {
+    type Err = UpNextError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::try_from(s.to_string()).map_err(UpNextError::ConfigError)
+    }
+}
+
+impl std::fmt::Display for WatchedThreshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchedThreshold::Percent(percent) => write!(f, "at least {percent}% has to be played"),
+            WatchedThreshold::Remaining(seconds) => {
+                write!(
+                    f,
+                    "playback has to reach the last {}",
+                    crate::utils::format_seconds(*seconds)
+                )
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for WatchedThreshold {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, String> {
+        let s = s.trim();
+        let invalid = || {
+            format!(
+                "Invalid watched threshold \"{s}\", expected a percentage like \"90%\" or a time before the end like \"3m\" or \"180s\""
+            )
+        };
+        if let Some(percent) = s.strip_suffix('%') {
+            match percent.trim().parse::<u8>() {
+                Ok(percent) if percent <= 100 => Ok(WatchedThreshold::Percent(percent)),
+                _ => Err(invalid()),
+            }
+        } else if let Some(minutes) = s.strip_suffix('m') {
+            let minutes: u64 = minutes.trim().parse().map_err(|_| invalid())?;
+            let seconds = minutes.checked_mul(60).ok_or_else(invalid)?;
+            Ok(WatchedThreshold::Remaining(seconds))
+        } else if let Some(seconds) = s.strip_suffix('s') {
+            let seconds: u64 = seconds.trim().parse().map_err(|_| invalid())?;
+            Ok(WatchedThreshold::Remaining(seconds))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl From<WatchedThreshold> for String {
+    fn from(threshold: WatchedThreshold) -> Self {
+        match threshold {
+            WatchedThreshold::Percent(percent) => format!("{percent}%"),
+            WatchedThreshold::Remaining(seconds) => format!("{seconds}s"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SeriesList {
+    #[serde(default)]
+    pub settings: Settings,
+    #[serde(default)]
     pub series: Vec<Series>,
 }
 
 impl SeriesList {
     pub fn new() -> Self {
-        SeriesList { series: Vec::new() }
+        SeriesList {
+            settings: Settings::default(),
+            series: Vec::new(),
+        }
     }
 
     pub fn add_series(&mut self, path: String) -> Result<()> {
@@ -24,7 +382,18 @@ impl SeriesList {
         }
         self.series.push(Series {
             path,
+            name: None,
+            aliases: None,
             next_episode: 1,
+            position_seconds: None,
+            audio_track: None,
+            subtitle_track: None,
+            last_watched_file: None,
+            last_watched_size: None,
+            episode_count: None,
+            last_watched: None,
+            status: None,
+            settings: SeriesSettings::default(),
         });
         Ok(())
     }
@@ -37,33 +406,35 @@ impl SeriesList {
         self.series
             .iter()
             .position(|s| s.path == path)
-            .ok_or_else(|| UpNextError::MissingSeries)
-    }
-
-    pub fn at(&self, index: usize) -> Result<&Series> {
-        self.series
-            .get(index)
-            .ok_or_else(|| UpNextError::MissingSeries)
-    }
-
-    pub fn at_mut(&mut self, index: usize) -> Result<&mut Series> {
-        self.series
-            .get_mut(index)
-            .ok_or_else(|| UpNextError::MissingSeries)
+            .ok_or(UpNextError::MissingSeries)
     }
 
     pub fn find_series_mut(&mut self, path: &str) -> Result<&mut Series> {
         self.series
             .iter_mut()
             .find(|s| s.path == path)
-            .ok_or_else(|| UpNextError::MissingSeries)
+            .ok_or(UpNextError::MissingSeries)
     }
 
     pub fn find_series(&self, path: &str) -> Result<&Series> {
         self.series
             .iter()
             .find(|s| s.path == path)
-            .ok_or_else(|| UpNextError::MissingSeries)
+            .ok_or(UpNextError::MissingSeries)
+    }
+
+    /// Series whose path, name or an alias contains `search_term`, ignoring case.
+    pub fn search(&self, search_term: &str) -> Vec<&Series> {
+        let lower_search_term = search_term.to_lowercase();
+        self.series
+            .iter()
+            .filter(|s| {
+                [s.path.as_str(), s.title()]
+                    .into_iter()
+                    .chain(s.aliases.iter().flatten().map(String::as_str))
+                    .any(|text| text.to_lowercase().contains(&lower_search_term))
+            })
+            .collect()
     }
 
     pub fn contains_path(&self, path: &str) -> bool {
@@ -1,8 +1,29 @@
-use crate::tests::utils::test;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::commands::discovery::NaturalName;
+use crate::commands::naming::EpisodeName;
+use crate::commands::player::{self, Playback};
+use crate::errors::UpNextError;
+use crate::persistence;
+use crate::schema::WatchedThreshold;
+use crate::tests::utils::{app_command, fake_mpv_ipc, scratch_dir, test, test_in_dir};
+
+static SHOW_DIR: &str = "test-resources/fixtures/show";
+static SEASONS_DIR: &str = "test-resources/fixtures/seasons";
+static NUMBERING_DIR: &str = "test-resources/fixtures/numbering";
+static ORDERED_DIR: &str = "test-resources/fixtures/ordered";
+static MIXED_DIR: &str = "test-resources/fixtures/mixed";
+static DOWNLOADS_DIR: &str = "test-resources/fixtures/downloads";
+static RELEASE_DIR: &str = "test-resources/fixtures/[Group] The.Show.2019.1080p.WEB-DL.x264-GROUP";
+static UNPADDED_DIR: &str = "test-resources/fixtures/unpadded";
+static FAKE_VLC: &str = "vlc:PATH/test-resources/fixtures/fake-vlc.sh";
 
 #[test]
 fn test_init() {
-    test("test_init", &vec!["init"]);
+    test_in_dir("test_init", &vec!["init"], SHOW_DIR, &[]);
 }
 
 #[test]
@@ -35,13 +56,1104 @@ fn test_set_next_episode() {
     test("test_set_next_episode", &vec!["set", "42"]);
 }
 
+#[test]
+fn test_next_stopped_early_saves_position() {
+    test_in_dir(
+        "test_next_stopped_early_saves_position",
+        &vec!["next"],
+        SHOW_DIR,
+        &[
+            (crate::PLAYER_ENV_VAR_NAME, FAKE_VLC),
+            ("FAKE_PLAYER_WATCH_SECONDS", "120"),
+            ("FAKE_PLAYER_DURATION", "1500"),
+        ],
+    );
+}
+
+#[test]
+fn test_next_resumes_from_position() {
+    test_in_dir(
+        "test_next_resumes_from_position",
+        &vec!["next"],
+        SHOW_DIR,
+        &[
+            (crate::PLAYER_ENV_VAR_NAME, FAKE_VLC),
+            ("FAKE_PLAYER_WATCH_SECONDS", "300"),
+            ("FAKE_PLAYER_DURATION", "1500"),
+        ],
+    );
+}
+
+#[test]
+fn test_next_finished_clears_position() {
+    test_in_dir(
+        "test_next_finished_clears_position",
+        &vec!["next"],
+        SHOW_DIR,
+        &[
+            (crate::PLAYER_ENV_VAR_NAME, FAKE_VLC),
+            ("FAKE_PLAYER_WATCH_SECONDS", "1380"),
+            ("FAKE_PLAYER_DURATION", "1500"),
+        ],
+    );
+}
+
+#[test]
+fn test_next_below_watched_threshold() {
+    test_in_dir(
+        "test_next_below_watched_threshold",
+        &vec!["next"],
+        SHOW_DIR,
+        &[
+            (crate::PLAYER_ENV_VAR_NAME, FAKE_VLC),
+            (crate::WATCHED_THRESHOLD_ENV_VAR_NAME, "3m"),
+            ("FAKE_PLAYER_WATCH_SECONDS", "1300"),
+            ("FAKE_PLAYER_DURATION", "1500"),
+        ],
+    );
+}
+
+#[test]
+fn test_next_within_watched_threshold() {
+    test_in_dir(
+        "test_next_within_watched_threshold",
+        &vec!["next"],
+        SHOW_DIR,
+        &[
+            (crate::PLAYER_ENV_VAR_NAME, FAKE_VLC),
+            (crate::WATCHED_THRESHOLD_ENV_VAR_NAME, "5m"),
+            ("FAKE_PLAYER_WATCH_SECONDS", "1300"),
+            ("FAKE_PLAYER_DURATION", "1500"),
+        ],
+    );
+}
+
+#[test]
+fn test_invalid_watched_threshold() {
+    test_in_dir(
+        "test_invalid_watched_threshold",
+        &vec!["next"],
+        SHOW_DIR,
+        &[
+            (crate::PLAYER_ENV_VAR_NAME, FAKE_VLC),
+            (crate::WATCHED_THRESHOLD_ENV_VAR_NAME, "ninety"),
+        ],
+    );
+}
+
+#[test]
+fn test_player_template() {
+    test_in_dir("test_player_template", &vec!["next"], SHOW_DIR, &[]);
+}
+
+#[test]
+fn test_player_template_unknown_placeholder() {
+    test_in_dir(
+        "test_player_template_unknown_placeholder",
+        &vec!["next"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_player_template_missing_program() {
+    test_in_dir(
+        "test_player_template_missing_program",
+        &vec!["next"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_settings_preserved_on_write() {
+    test("test_settings_preserved_on_write", &vec!["inc"]);
+}
+
+#[test]
+fn test_settings_invalid() {
+    test("test_settings_invalid", &vec!["info"]);
+}
+
+#[test]
+fn test_settings_extensions() {
+    test_in_dir("test_settings_extensions", &vec!["next"], SHOW_DIR, &[]);
+}
+
+#[test]
+fn test_settings_overridden_by_flag() {
+    test_in_dir(
+        "test_settings_overridden_by_flag",
+        &vec!["next", "--player", "true", "--no-fullscreen"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_series_settings_preserved_on_write() {
+    test("test_series_settings_preserved_on_write", &vec!["set", "3"]);
+}
+
+#[test]
+fn test_series_settings_override_global_settings() {
+    test_in_dir(
+        "test_series_settings_override_global_settings",
+        &vec!["next"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_info_resync_after_episodes_changed() {
+    test_in_dir(
+        "test_info_resync_after_episodes_changed",
+        &vec!["info"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_info_next_episode_edited_by_hand() {
+    test_in_dir(
+        "test_info_next_episode_edited_by_hand",
+        &vec!["info"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_info_last_watched_missing() {
+    test_in_dir(
+        "test_info_last_watched_missing",
+        &vec!["info"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_info_unpadded_last_watched_deleted() {
+    test_in_dir(
+        "test_info_unpadded_last_watched_deleted",
+        &vec!["info"],
+        UNPADDED_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_next_last_watched_missing() {
+    test_in_dir(
+        "test_next_last_watched_missing",
+        &vec!["next", "--player", "true"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_increment_records_last_watched() {
+    test_in_dir(
+        "test_increment_records_last_watched",
+        &vec!["inc"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_queue_most_recent_first() {
+    test_in_dir(
+        "test_queue_most_recent_first",
+        &vec!["queue"],
+        SHOW_DIR,
+        &[("TZ", "UTC")],
+    );
+}
+
+#[test]
+fn test_queue_nothing_to_continue() {
+    test_in_dir("test_queue_nothing_to_continue", &vec![], SHOW_DIR, &[]);
+}
+
+#[test]
+fn test_info_series_by_name() {
+    test_in_dir(
+        "test_info_series_by_name",
+        &vec!["info", "--series", "SHOW"],
+        ".",
+        &[],
+    );
+}
+
+#[test]
+fn test_info_series_by_path() {
+    test_in_dir(
+        "test_info_series_by_path",
+        &vec!["info", "-s", "fixtures/show"],
+        "test-resources",
+        &[],
+    );
+}
+
+#[test]
+fn test_set_series_by_index() {
+    test_in_dir(
+        "test_set_series_by_index",
+        &vec!["set", "3", "--series", "#2"],
+        ".",
+        &[],
+    );
+}
+
+#[test]
+fn test_series_ambiguous() {
+    test_in_dir(
+        "test_series_ambiguous",
+        &vec!["inc", "--series", "fixtures"],
+        ".",
+        &[],
+    );
+}
+
+#[test]
+fn test_series_unknown() {
+    test_in_dir(
+        "test_series_unknown",
+        &vec!["remove", "--series", "nope"],
+        ".",
+        &[],
+    );
+}
+
+#[test]
+fn test_next_series_from_other_directory() {
+    test_in_dir(
+        "test_next_series_from_other_directory",
+        &vec!["next", "--series", "numb"],
+        SHOW_DIR,
+        &[
+            (crate::PLAYER_ENV_VAR_NAME, FAKE_VLC),
+            ("FAKE_PLAYER_WATCH_SECONDS", "1500"),
+            ("FAKE_PLAYER_DURATION", "1500"),
+        ],
+    );
+}
+
+#[test]
+fn test_list() {
+    test_in_dir("test_list", &vec!["list"], ".", &[("TZ", "UTC")]);
+}
+
+#[test]
+fn test_list_sorted_unfinished() {
+    test_in_dir(
+        "test_list_sorted_unfinished",
+        &vec!["list", "--sort", "name", "--filter", "unfinished"],
+        ".",
+        &[("TZ", "UTC")],
+    );
+}
+
+#[test]
+fn test_list_raw() {
+    test_in_dir("test_list_raw", &vec!["list", "--raw"], ".", &[]);
+}
+
+#[test]
+fn test_info_json() {
+    test_in_dir(
+        "test_info_json",
+        &vec!["info", "--output", "json"],
+        SHOW_DIR,
+        &[("TZ", "UTC")],
+    );
+}
+
+#[test]
+fn test_list_json() {
+    test_in_dir(
+        "test_list_json",
+        &vec!["list", "--filter", "finished", "--output", "json"],
+        ".",
+        &[("TZ", "UTC")],
+    );
+}
+
+#[test]
+fn test_inc_json() {
+    test_in_dir(
+        "test_inc_json",
+        &vec!["--output", "json", "inc", "--series", "kids"],
+        ".",
+        &[("TZ", "UTC")],
+    );
+}
+
+#[test]
+fn test_json_error() {
+    test_in_dir(
+        "test_json_error",
+        &vec!["info", "--series", "nope", "--output", "json"],
+        ".",
+        &[],
+    );
+}
+
+#[test]
+fn test_json_unsupported_command() {
+    test_in_dir(
+        "test_json_unsupported_command",
+        &vec!["remove", "--output", "json"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_status_dropped() {
+    test_in_dir(
+        "test_status_dropped",
+        &vec!["status", "dropped"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_list_hides_dropped() {
+    test_in_dir("test_list_hides_dropped", &vec!["list"], ".", &[]);
+}
+
+#[test]
+fn test_queue_skips_paused_and_dropped() {
+    test_in_dir(
+        "test_queue_skips_paused_and_dropped",
+        &vec!["queue"],
+        ".",
+        &[],
+    );
+}
+
+#[test]
+fn test_next_last_episode_completes() {
+    test_in_dir(
+        "test_next_last_episode_completes",
+        &vec!["next"],
+        SHOW_DIR,
+        &[
+            (crate::PLAYER_ENV_VAR_NAME, FAKE_VLC),
+            ("FAKE_PLAYER_WATCH_SECONDS", "1500"),
+            ("FAKE_PLAYER_DURATION", "1500"),
+        ],
+    );
+}
+
+#[test]
+fn test_next_completed_series() {
+    test_in_dir("test_next_completed_series", &vec!["next"], SHOW_DIR, &[]);
+}
+
+#[test]
+fn test_set_reopens_completed_series() {
+    test_in_dir(
+        "test_set_reopens_completed_series",
+        &vec!["set", "2"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_history() {
+    test_in_dir("test_history", &vec!["history"], ".", &[("TZ", "UTC")]);
+}
+
+#[test]
+fn test_history_series_since() {
+    test_in_dir(
+        "test_history_series_since",
+        &vec!["history", "--series", "kids", "--since", "2026-03-01"],
+        ".",
+        &[("TZ", "UTC")],
+    );
+}
+
+#[test]
+fn test_history_json() {
+    test_in_dir(
+        "test_history_json",
+        &vec!["history", "--since", "2026-03-03", "--output", "json"],
+        ".",
+        &[("TZ", "UTC")],
+    );
+}
+
+#[test]
+fn test_history_invalid_since() {
+    test_in_dir(
+        "test_history_invalid_since",
+        &vec!["history", "--since", "3 days"],
+        ".",
+        &[],
+    );
+}
+
+#[test]
+fn test_stats() {
+    test_in_dir("test_stats", &vec!["stats"], ".", &[("TZ", "UTC")]);
+}
+
+#[test]
+fn test_stats_json() {
+    test_in_dir(
+        "test_stats_json",
+        &vec!["stats", "--since", "2026-03-02", "--output", "json"],
+        ".",
+        &[("TZ", "UTC")],
+    );
+}
+
+#[test]
+fn test_stats_empty() {
+    test_in_dir("test_stats_empty", &vec!["stats"], ".", &[]);
+}
+
+#[test]
+fn test_undo_set() {
+    test_in_dir("test_undo_set", &vec!["undo"], ".", &[]);
+}
+
+#[test]
+fn test_undo_set_keeps_later_edits() {
+    test_in_dir("test_undo_set_keeps_later_edits", &vec!["undo"], ".", &[]);
+}
+
+#[test]
+fn test_undo_remove() {
+    test_in_dir("test_undo_remove", &vec!["undo"], ".", &[]);
+}
+
+#[test]
+fn test_redo_set() {
+    test_in_dir("test_redo_set", &vec!["redo"], ".", &[]);
+}
+
+#[test]
+fn test_undo_nothing() {
+    test_in_dir("test_undo_nothing", &vec!["undo"], ".", &[]);
+}
+
+#[test]
+fn test_init_recursive() {
+    test_in_dir(
+        "test_init_recursive",
+        &vec!["init", "--recursive"],
+        SEASONS_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_init_derives_name() {
+    test_in_dir("test_init_derives_name", &vec!["init"], RELEASE_DIR, &[]);
+}
+
+#[test]
+fn test_rename() {
+    test_in_dir(
+        "test_rename",
+        &vec!["rename", "Kids Show", "--alias", "kids", "-a", "ks"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_info_series_by_alias() {
+    test_in_dir(
+        "test_info_series_by_alias",
+        &vec!["info", "--series", "KS"],
+        ".",
+        &[],
+    );
+}
+
+#[test]
+fn test_info_recursive_shows_season_and_episode() {
+    test_in_dir(
+        "test_info_recursive_shows_season_and_episode",
+        &vec!["info"],
+        SEASONS_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_next_recursive_tracks_season_directory() {
+    test_in_dir(
+        "test_next_recursive_tracks_season_directory",
+        &vec!["next"],
+        SEASONS_DIR,
+        &[
+            (crate::PLAYER_ENV_VAR_NAME, FAKE_VLC),
+            ("FAKE_PLAYER_WATCH_SECONDS", "1500"),
+            ("FAKE_PLAYER_DURATION", "1500"),
+        ],
+    );
+}
+
+#[test]
+fn test_info_orders_by_episode_number() {
+    test_in_dir(
+        "test_info_orders_by_episode_number",
+        &vec!["info"],
+        NUMBERING_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_watched_threshold_parser() {
+    let cases = [
+        ("90%", Some(WatchedThreshold::Percent(90))),
+        (" 100 % ", Some(WatchedThreshold::Percent(100))),
+        ("101%", None),
+        ("3m", Some(WatchedThreshold::Remaining(180))),
+        ("180s", Some(WatchedThreshold::Remaining(180))),
+        ("307445734561825861m", None),
+        ("ninety", None),
+    ];
+    for (threshold, expected) in cases {
+        assert_eq!(threshold.parse().ok(), expected, "{threshold}");
+    }
+}
+
+#[test]
+fn test_episode_name_parser() {
+    let episode = |season, episode, last_episode| {
+        Some(EpisodeName {
+            season,
+            episode,
+            last_episode,
+        })
+    };
+    let cases = [
+        ("Show S01E05 720p.mkv", episode(Some(1), 5, 5)),
+        ("show.s2.e10.x264.mkv", episode(Some(2), 10, 10)),
+        ("Show S01E01-E02.mkv", episode(Some(1), 1, 2)),
+        ("Show S01E03E04.mkv", episode(Some(1), 3, 4)),
+        ("Show S01E05-06.mkv", episode(Some(1), 5, 6)),
+        ("Show S00E01 Pilot.mkv", episode(Some(0), 1, 1)),
+        ("Show - 1x05 - Title.mkv", episode(Some(1), 5, 5)),
+        ("Show 2x01-2x02.mkv", episode(Some(2), 1, 2)),
+        ("Show 1920x1080 Episode 12.mkv", episode(None, 12, 12)),
+        ("Show Ep.3.mkv", episode(None, 3, 3)),
+        ("Show 2009 Special.mkv", None),
+        ("Epic Show.mkv", None),
+    ];
+    for (file_name, expected) in cases {
+        assert_eq!(EpisodeName::parse(file_name), expected, "{file_name}");
+    }
+}
+
+#[test]
+fn test_info_lexical_sort_order() {
+    test_in_dir(
+        "test_info_lexical_sort_order",
+        &vec!["info"],
+        NUMBERING_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_info_manual_order_file() {
+    test_in_dir(
+        "test_info_manual_order_file",
+        &vec!["info"],
+        ORDERED_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_order_regenerate_list() {
+    test_in_dir(
+        "test_order_regenerate_list",
+        &vec!["order", "regenerate"],
+        NUMBERING_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_next_manual_order_missing() {
+    test_in_dir(
+        "test_next_manual_order_missing",
+        &vec!["next", "--player", "true"],
+        SHOW_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_files_lists_ignored_entries() {
+    test_in_dir(
+        "test_files_lists_ignored_entries",
+        &vec!["files"],
+        MIXED_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_info_skips_non_video_files() {
+    test_in_dir(
+        "test_info_skips_non_video_files",
+        &vec!["info"],
+        MIXED_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_files_ignore_patterns() {
+    test_in_dir(
+        "test_files_ignore_patterns",
+        &vec!["files"],
+        DOWNLOADS_DIR,
+        &[],
+    );
+}
+
+#[test]
+fn test_natural_sort_order() {
+    let mut names: Vec<NaturalName> = [
+        "Episode 10.mkv",
+        "episode 2.mkv",
+        "Episode 02b.mkv",
+        "Episode 1.mkv",
+        "Episode 100.mkv",
+    ]
+    .iter()
+    .map(|name| NaturalName(name.to_string()))
+    .collect();
+    names.sort();
+    let names: Vec<&str> = names.iter().map(|name| name.0.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "Episode 1.mkv",
+            "episode 2.mkv",
+            "Episode 02b.mkv",
+            "Episode 10.mkv",
+            "Episode 100.mkv"
+        ]
+    );
+}
+
+#[test]
+fn test_player_from_spec() {
+    let describe = |spec: &str| format!("{:?}", player::from_spec(spec, true, vec![]).unwrap());
+    assert_eq!(
+        describe("vlc"),
+        "Vlc { executable: None, fullscreen: true, args: [] }"
+    );
+    assert_eq!(
+        describe("mpv:/opt/mpv/bin/mpv"),
+        "Mpv { executable: Some(\"/opt/mpv/bin/mpv\"), fullscreen: true, args: [] }"
+    );
+    assert_eq!(
+        describe(" mplayer "),
+        "Mplayer { executable: None, fullscreen: true, args: [] }"
+    );
+
+    // A colon in a template does not make it a built-in player with an executable
+    let template = player::from_spec("sh -c true --geometry=0:0", true, vec![]).unwrap();
+    assert_eq!(template.name(), "sh");
+    assert!(format!("{template:?}").contains("0:0"));
+
+    assert!(matches!(
+        player::from_spec("", true, vec![]),
+        Err(UpNextError::ConfigError(_))
+    ));
+}
+
+#[test]
+fn test_mpv_ipc_quit_early() {
+    let (playback, requests) = fake_mpv_ipc(
+        "test_mpv_ipc_quit_early",
+        &[
+            r#"{"event":"property-change","id":0,"name":"time-pos","data":1.5}"#,
+            r#"{"event":"property-change","id":1,"name":"duration","data":1500.4}"#,
+            r#"{"event":"property-change","id":2,"name":"aid","data":2}"#,
+            r#"{"event":"property-change","id":3,"name":"sid","data":false}"#,
+            r#"{"event":"property-change","id":0,"name":"time-pos","data":612.8}"#,
+            r#"{"event":"end-file","reason":"quit"}"#,
+            r#"{"event":"property-change","id":0,"name":"time-pos","data":null}"#,
+            r#"{"event":"property-change","id":2,"name":"aid","data":"auto"}"#,
+        ],
+    );
+    assert_eq!(
+        requests,
+        vec![
+            r#"{"command":["observe_property",0,"time-pos"]}"#,
+            r#"{"command":["observe_property",1,"duration"]}"#,
+            r#"{"command":["observe_property",2,"aid"]}"#,
+            r#"{"command":["observe_property",3,"sid"]}"#,
+        ]
+    );
+    assert_eq!(
+        playback,
+        Playback {
+            position: Some(612),
+            duration: Some(1500),
+            reached_end: false,
+            audio_track: Some("2".to_string()),
+            subtitle_track: Some("no".to_string()),
+        }
+    );
+}
+
+#[test]
+fn test_mpv_ipc_end_of_file() {
+    let (playback, _) = fake_mpv_ipc(
+        "test_mpv_ipc_end_of_file",
+        &[
+            r#"{"event":"property-change","id":1,"name":"duration","data":1500.4}"#,
+            r#"{"event":"property-change","id":3,"name":"sid","data":1}"#,
+            r#"{"event":"property-change","id":0,"name":"time-pos","data":1499.9}"#,
+            r#"{"event":"end-file","reason":"eof"}"#,
+        ],
+    );
+    assert_eq!(
+        playback,
+        Playback {
+            position: Some(1499),
+            duration: Some(1500),
+            reached_end: true,
+            audio_track: None,
+            subtitle_track: Some("1".to_string()),
+        }
+    );
+}
+
+#[test]
+fn test_write_toml_file_through_symlink_keeps_backups() {
+    let dir = scratch_dir("test_write_toml_file_through_symlink_keeps_backups");
+    let target = dir.join("target.toml");
+    let link = dir.join("link.toml");
+    fs::write(
+        &target,
+        "[[series]]\npath = \"/videos/show\"\nnext_episode = 1\n",
+    )
+    .unwrap();
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+
+    for next_episode in 2..=5 {
+        let mut series_list = persistence::read_toml_file(&link).unwrap();
+        series_list.series[0].next_episode = next_episode;
+        persistence::write_toml_file(&link, &series_list).unwrap();
+    }
+
+    assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "[[series]]\npath = \"/videos/show\"\nnext_episode = 5\n"
+    );
+    let mode = fs::metadata(&target).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    for (n, next_episode) in [(1, 4), (2, 3), (3, 2)] {
+        let backup = fs::read_to_string(persistence::backup_path(&target, n)).unwrap();
+        assert!(backup.contains(&format!("next_episode = {next_episode}")));
+    }
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "link.toml",
+            "target.toml",
+            "target.toml.bak.1",
+            "target.toml.bak.2",
+            "target.toml.bak.3"
+        ]
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_read_empty_toml_file_with_backup() {
+    let dir = scratch_dir("test_read_empty_toml_file_with_backup");
+    let path = dir.join("upnext.toml");
+    fs::write(&path, "").unwrap();
+    assert!(persistence::read_toml_file(&path)
+        .unwrap()
+        .series
+        .is_empty());
+
+    fs::write(
+        persistence::backup_path(&path, 1),
+        "[[series]]\npath = \"/videos/show\"\nnext_episode = 3\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        persistence::read_toml_file(&path),
+        Err(UpNextError::SchemaError(_))
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_next_records_history_when_player_fails() {
+    let dir = scratch_dir("test_next_records_history_when_player_fails");
+    let toml_path = dir.join("upnext.toml");
+    let show_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(SHOW_DIR);
+    fs::write(
+        &toml_path,
+        format!(
+            "[[series]]\npath = \"{}\"\nnext_episode = 1\n",
+            show_dir.display()
+        ),
+    )
+    .unwrap();
+
+    let output = app_command(&toml_path)
+        .args(["next", "--player", "false"])
+        .current_dir(&show_dir)
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Player error"));
+    let history = fs::read_to_string(dir.join("upnext-history.jsonl")).unwrap();
+    let entries: Vec<serde_json::Value> = history
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["file"], "Show S01E01.mkv");
+    assert_eq!(entries[0]["completed"], false);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_play_keeps_inc_made_while_playing() {
+    let dir = scratch_dir("test_play_keeps_inc_made_while_playing");
+    let toml_path = dir.join("upnext.toml");
+    let started_path = dir.join("started");
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let show_dir = crate_dir.join(SHOW_DIR);
+    fs::write(
+        &toml_path,
+        format!(
+            "[[series]]\npath = \"{}\"\nnext_episode = 1\n",
+            show_dir.display()
+        ),
+    )
+    .unwrap();
+
+    let play = app_command(&toml_path)
+        .args(["play", "--delay-seconds", "0", "--player"])
+        .arg(crate_dir.join("test-resources/fixtures/slow-player.sh"))
+        .current_dir(&show_dir)
+        .env("FAKE_PLAYER_STARTED", &started_path)
+        .env("FAKE_PLAYER_SECONDS", "2")
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if started_path.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert!(started_path.exists(), "the player did not start");
+    let inc = app_command(&toml_path)
+        .args(["inc", "2", "-s", "show"])
+        .output()
+        .unwrap();
+    assert!(
+        inc.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&inc.stderr)
+    );
+    let play = play.wait_with_output().unwrap();
+
+    // The inc is kept and the session goes on from there, with the third episode
+    let stdout = String::from_utf8_lossy(&play.stdout);
+    assert!(
+        stdout.contains("was changed to 3 while it played"),
+        "{stdout}"
+    );
+    assert!(
+        !stdout.contains("Starting episode \"Show S01E02.mkv\""),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("Starting episode \"Show S01E03.mkv\""),
+        "{stdout}"
+    );
+    let content = fs::read_to_string(&toml_path).unwrap();
+    assert!(content.contains("next_episode = 4"), "{content}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_next_releases_lock_while_asking() {
+    let dir = scratch_dir("test_next_releases_lock_while_asking");
+    let toml_path = dir.join("upnext.toml");
+    let show_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(SHOW_DIR);
+    // The last watched episode is gone, so `next` asks where to continue
+    fs::write(
+        &toml_path,
+        format!(
+            "[[series]]\npath = \"{}\"\nnext_episode = 2\nlast_watched_file = \"Show S01E05.mkv\"\n",
+            show_dir.display()
+        ),
+    )
+    .unwrap();
+
+    let mut next = app_command(&toml_path)
+        .args(["next", "--player", "true"])
+        .current_dir(&show_dir)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(next.stdout.take().unwrap());
+    let mut line = String::new();
+    while !line.contains("Continue from there?") {
+        line.clear();
+        assert_ne!(stdout.read_line(&mut line).unwrap(), 0, "no question asked");
+    }
+    let lock = persistence::lock(&toml_path, || panic!("the lock is held while asking"));
+    drop(lock);
+    writeln!(next.stdin.take().unwrap(), "n").unwrap();
+    assert!(next.wait().unwrap().success());
+
+    let content = fs::read_to_string(&toml_path).unwrap();
+    assert!(content.contains("next_episode = 3"), "{content}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_order_edit_releases_lock_while_editing() {
+    let dir = scratch_dir("test_order_edit_releases_lock_while_editing");
+    let toml_path = dir.join("upnext.toml");
+    let started_path = dir.join("started");
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let show_dir = crate_dir.join(SHOW_DIR);
+    fs::write(
+        &toml_path,
+        format!(
+            "[[series]]\npath = \"{}\"\nnext_episode = 1\nsort = \"manual\"\norder = [\"Show S01E02.mkv\"]\n",
+            show_dir.display()
+        ),
+    )
+    .unwrap();
+
+    // The slow player stands in for an editor which stays open for a while
+    let mut edit = app_command(&toml_path)
+        .args(["order", "edit"])
+        .current_dir(&show_dir)
+        .env(
+            "VISUAL",
+            crate_dir.join("test-resources/fixtures/slow-player.sh"),
+        )
+        .env("FAKE_PLAYER_STARTED", &started_path)
+        .env("FAKE_PLAYER_SECONDS", "2")
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if started_path.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert!(started_path.exists(), "the editor did not start");
+    let lock = persistence::lock(&toml_path, || panic!("the lock is held while editing"));
+    drop(lock);
+    assert!(edit.wait().unwrap().success());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_next_warns_about_episode_name_which_is_not_utf8() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = scratch_dir("test_next_warns_about_episode_name_which_is_not_utf8");
+    let toml_path = dir.join("upnext.toml");
+    let series_dir = dir.join("series");
+    fs::create_dir(&series_dir).unwrap();
+    let file_name = std::ffi::OsStr::from_bytes(b"Show S01E02 \xff.mkv");
+    fs::write(series_dir.join(file_name), "").unwrap();
+    fs::write(
+        &toml_path,
+        format!(
+            "[[series]]\npath = \"{}\"\nnext_episode = 1\n",
+            series_dir.display()
+        ),
+    )
+    .unwrap();
+
+    let output = app_command(&toml_path)
+        .args(["next", "--player", "true"])
+        .current_dir(&series_dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(
+        stdout.contains("Warning: The file \"Show S01E02 \u{FFFD}.mkv\""),
+        "{stdout}"
+    );
+    let content = fs::read_to_string(&toml_path).unwrap();
+    assert!(content.contains("next_episode = 2"), "{content}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_lock_waits_for_other_holder() {
+    let dir = scratch_dir("test_lock_waits_for_other_holder");
+    let path = dir.join("upnext.toml");
+    let held = persistence::lock(&path, || panic!("nobody holds the lock yet")).unwrap();
+
+    let (waiting_sender, waiting) = std::sync::mpsc::channel();
+    let other_path = path.clone();
+    let other = std::thread::spawn(move || {
+        persistence::lock(&other_path, || waiting_sender.send(()).unwrap()).map(|_| ())
+    });
+    waiting
+        .recv_timeout(std::time::Duration::from_secs(5))
+        .unwrap();
+    drop(held);
+    assert!(other.join().unwrap().is_ok());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
 #[cfg(test)]
 mod utils {
     use std::fs;
-    use std::path::PathBuf;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
     use std::process::Command;
     use std::sync::Once;
 
+    use crate::commands::player::{mpv, Playback};
+    use crate::persistence;
+
     static INIT: Once = Once::new();
 
     fn build() {
@@ -54,6 +1166,12 @@ mod utils {
     }
 
     pub fn test(name: &str, args: &Vec<&str>) {
+        test_in_dir(name, args, "", &[]);
+    }
+
+    /// Like `test`, but runs the app in `dir` (relative to the crate root) with extra
+    /// environment variables. `PATH` in the values is replaced with the crate root.
+    pub fn test_in_dir(name: &str, args: &Vec<&str>, dir: &str, envs: &[(&str, &str)]) {
         build();
         let (toml_path, expected_stdout, expected_stderr, before, after) = read_test_files(name);
         let (stdout, stderr, file_content) = {
@@ -62,7 +1180,18 @@ mod utils {
             } else {
                 delete_toml_file(PathBuf::from(&toml_path));
             }
-            run_app(args, &toml_path)
+            for fixture in SIDE_FIXTURES {
+                if let Some(content) = read_side_fixture(name, fixture) {
+                    set_toml_file(content, side_file_path(&toml_path, fixture));
+                }
+            }
+            let mut cwd = PathBuf::from(cargo_manifest_dir());
+            cwd.push(dir);
+            let envs: Vec<(&str, String)> = envs
+                .iter()
+                .map(|(key, value)| (*key, value.replace("PATH", &cargo_manifest_dir())))
+                .collect();
+            run_app(args, &toml_path, &cwd, &envs)
         };
         println!("stdout: {}", String::from_utf8_lossy(&stdout));
         println!("stderr: {}", String::from_utf8_lossy(&stderr));
@@ -72,9 +1201,99 @@ mod utils {
         if let Some(expected_stderr) = expected_stderr {
             assert_eq!(String::from_utf8_lossy(&stderr), expected_stderr);
         }
+        let file_content = match &after {
+            Some(after) if after.contains(TIMESTAMP) => file_content.map(mask_timestamps),
+            _ => file_content,
+        };
         assert_eq!(file_content, after);
 
-        delete_toml_file(PathBuf::from(&toml_path));
+        delete_test_files(&toml_path);
+    }
+
+    /// Optional history and undo journal the app starts with, next to `before.toml`.
+    const SIDE_FIXTURES: [&str; 2] = ["history.jsonl", "journal.json"];
+
+    /// The file the app keeps next to the toml file at `toml_path`, like the history.
+    fn side_file_path(toml_path: &str, fixture: &str) -> PathBuf {
+        PathBuf::from(toml_path.replace("res.toml", &format!("res-{fixture}")))
+    }
+
+    fn delete_test_files(toml_path: &str) {
+        delete_toml_file(PathBuf::from(toml_path));
+        for fixture in SIDE_FIXTURES {
+            delete_toml_file(side_file_path(toml_path, fixture));
+        }
+        delete_toml_file(persistence::lock_path(Path::new(toml_path)));
+        for n in 1..=persistence::BACKUPS {
+            delete_toml_file(persistence::backup_path(Path::new(toml_path), n));
+        }
+    }
+
+    fn read_side_fixture(test_name: &str, fixture: &str) -> Option<String> {
+        let mut path = PathBuf::from(cargo_manifest_dir());
+        path.push("test-resources");
+        path.push(test_name);
+        path.push(fixture);
+        fs::read_to_string(path)
+            .ok()
+            .map(|content| content.replace("PATH", &cargo_manifest_dir()))
+    }
+
+    /// Placeholder in `after.toml` for the time an episode was played.
+    const TIMESTAMP: &str = "TIMESTAMP";
+
+    fn mask_timestamps(content: String) -> String {
+        content
+            .lines()
+            .map(|line| match line.split_once(" = ") {
+                Some(("last_watched", _)) => format!("last_watched = \"{TIMESTAMP}\""),
+                _ => line.to_string(),
+            })
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    /// Run the mpv IPC client against a fake mpv socket which answers the observe requests and
+    /// then sends `events`. Returns the playback and the requests received by the fake.
+    pub fn fake_mpv_ipc(name: &str, events: &[&str]) -> (Playback, Vec<String>) {
+        let socket_path = std::env::temp_dir().join(format!("upnext-{name}.sock"));
+        let _ = fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let events: Vec<String> = events.iter().map(ToString::to_string).collect();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut requests = vec![];
+            for request_id in 0..4 {
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                requests.push(request.trim_end().to_string());
+                writeln!(
+                    stream,
+                    r#"{{"data":null,"request_id":{request_id},"error":"success"}}"#
+                )
+                .unwrap();
+            }
+            for event in events {
+                writeln!(stream, "{event}").unwrap();
+            }
+            requests
+        });
+
+        let stream = UnixStream::connect(&socket_path).unwrap();
+        let playback = mpv::watch_ipc(stream).unwrap();
+        let requests = server.join().unwrap();
+        fs::remove_file(&socket_path).unwrap();
+        (playback, requests)
+    }
+
+    /// An empty directory for a test to create files in.
+    pub fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("upnext-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
     }
 
     fn cargo_manifest_dir() -> String {
@@ -91,12 +1310,28 @@ mod utils {
         fs::write(path, content).unwrap();
     }
 
-    fn run_app(args: &Vec<&str>, toml_path: &String) -> (Vec<u8>, Vec<u8>, Option<String>) {
+    /// The app using the toml file at `toml_path`, without input.
+    pub fn app_command(toml_path: &Path) -> Command {
+        build();
         let mut path = PathBuf::from(cargo_manifest_dir());
         path.push("target/debug/upnext");
-        let output = Command::new(path)
+        let mut command = Command::new(path);
+        command
+            .stdin(std::process::Stdio::null())
+            .env(crate::TOML_PATH_ENV_VAR_NAME, toml_path);
+        command
+    }
+
+    fn run_app(
+        args: &Vec<&str>,
+        toml_path: &String,
+        cwd: &PathBuf,
+        envs: &[(&str, String)],
+    ) -> (Vec<u8>, Vec<u8>, Option<String>) {
+        let output = app_command(Path::new(toml_path))
             .args(args)
-            .env(crate::TOML_PATH_ENV_VAR_NAME, toml_path.clone())
+            .current_dir(cwd)
+            .envs(envs.iter().map(|(key, value)| (key, value)))
             .output()
             .expect("Failed to execute command");
         let file_content = fs::read_to_string(toml_path).unwrap();
@@ -145,9 +1380,13 @@ mod utils {
             toml_path.into_os_string().into_string().unwrap()
         };
 
-        delete_toml_file(PathBuf::from(&toml_path));
+        delete_test_files(&toml_path);
         let number_of_files_in_dir = fs::read_dir(&path).unwrap().count();
-        let number_of_identified_test_files = res.iter().filter(|x| x.is_some()).count();
+        let number_of_identified_test_files = res.iter().filter(|x| x.is_some()).count()
+            + SIDE_FIXTURES
+                .iter()
+                .filter(|fixture| path.join(fixture).exists())
+                .count();
         assert_eq!(
             number_of_files_in_dir, number_of_identified_test_files,
             "Not all files in dir are identified as test files"
@@ -1,4 +1,5 @@
 use crate::errors::UpNextError;
+use crate::schema::Settings;
 
 pub(crate) fn get_toml_path() -> crate::errors::Result<String> {
     match std::env::var(crate::TOML_PATH_ENV_VAR_NAME) {
@@ -11,3 +12,48 @@ pub(crate) fn get_toml_path() -> crate::errors::Result<String> {
         },
     }
 }
+
+/// The watch history is kept next to the toml file, e.g. `~/.upnext-history.jsonl` for
+/// `~/.upnext.toml`.
+pub(crate) fn get_history_path() -> crate::errors::Result<String> {
+    path_next_to_toml("history.jsonl")
+}
+
+/// The undo journal is kept next to the toml file, e.g. `~/.upnext-journal.json`.
+pub(crate) fn get_journal_path() -> crate::errors::Result<String> {
+    path_next_to_toml("journal.json")
+}
+
+fn path_next_to_toml(suffix: &str) -> crate::errors::Result<String> {
+    let toml_path = get_toml_path()?;
+    let toml_path = std::path::Path::new(&toml_path);
+    let stem = toml_path
+        .file_stem()
+        .map_or_else(|| "upnext".into(), |stem| stem.to_string_lossy());
+    Ok(toml_path
+        .with_file_name(format!("{stem}-{suffix}"))
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// Format seconds as `h:mm:ss` or `m:ss`.
+pub(crate) fn format_seconds(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+/// Settings given through environment variables, these take precedence over the settings file.
+pub(crate) fn settings_from_env() -> crate::errors::Result<Settings> {
+    Ok(Settings {
+        player: std::env::var(crate::PLAYER_ENV_VAR_NAME).ok(),
+        watched_threshold: std::env::var(crate::WATCHED_THRESHOLD_ENV_VAR_NAME)
+            .ok()
+            .map(|threshold| threshold.parse())
+            .transpose()?,
+        ..Settings::default()
+    })
+}
@@ -0,0 +1,17 @@
+#!/bin/sh
+# Stand-in for VLC used in tests. Answers the remote control queries as if the episode was
+# stopped `FAKE_PLAYER_WATCH_SECONDS` after `--start-time`, then exits.
+start=0
+for arg in "$@"; do
+    case "$arg" in
+        --start-time=*) start="${arg#--start-time=}" ;;
+    esac
+done
+position=$((start + ${FAKE_PLAYER_WATCH_SECONDS:-0}))
+
+while read -r command; do
+    case "$command" in
+        get_time) echo "$position" ;;
+        get_length) echo "${FAKE_PLAYER_DURATION:-0}"; exit 0 ;;
+    esac
+done
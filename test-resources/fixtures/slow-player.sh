@@ -0,0 +1,5 @@
+#!/bin/sh
+# Stand-in for a player used in tests. Creates the file `FAKE_PLAYER_STARTED` once it plays,
+# then takes `FAKE_PLAYER_SECONDS` to watch the episode.
+touch "$FAKE_PLAYER_STARTED"
+sleep "${FAKE_PLAYER_SECONDS:-2}"